@@ -0,0 +1,558 @@
+use crate::{forward, interaction, settings};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Interval {
+    Green,
+    LagArrow,
+    Yellow,
+    AllRed,
+}
+
+// signals sharing an offset in the fixed-time plan run together
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stage {
+    pub offset_secs: f64,
+    pub veh_keys: Vec<[settings::Dir; 2]>,
+    pub ped_keys: Vec<[settings::Dir; 2]>,
+    pub lag_keys: Vec<[settings::Dir; 2]>,
+    pub lag_secs: f64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Controller {
+    pub stages: Vec<Stage>,
+    pub stage: usize,
+    pub interval: Interval,
+    pub interval_secs: f64,
+    pub gap_secs: f64,
+    pub veh_calls: Vec<bool>,
+    pub ped_calls: Vec<bool>,
+    pub walk_secs: Option<f64>,
+    pub lpi_secs: f64,
+    pub red_secs: Vec<f64>,
+    pub ped_red_secs: Vec<f64>,
+}
+
+impl Controller {
+    pub fn new(settings: &settings::Settings) -> Self {
+        // stages follow the plan before protected arrows hold their conflicts
+        let (veh_signals, ped_signals) = match &settings.signal_plan {
+            Some(plan) => plan.signals(),
+            None => (settings.veh_signals.clone(), settings.ped_signals.clone()),
+        };
+
+        let mut stages: Vec<Stage> = vec![];
+        let veh_keys = veh_signals
+            .iter()
+            .map(|v| (v.offset_secs, [v.src_dir, v.dst_dir], false));
+        let ped_keys = ped_signals
+            .iter()
+            .map(|v| (v.offset_secs, [v.src_dir, v.dst_dir], true));
+        for (offset_secs, key, is_ped) in veh_keys.chain(ped_keys) {
+            let i = match stages
+                .iter()
+                .position(|v| (v.offset_secs - offset_secs).abs() < 1e-6)
+            {
+                Some(i) => i,
+                None => {
+                    stages.push(Stage {
+                        offset_secs,
+                        veh_keys: vec![],
+                        ped_keys: vec![],
+                        lag_keys: vec![],
+                        lag_secs: 0.0,
+                    });
+                    stages.len() - 1
+                }
+            };
+            match is_ped {
+                false => stages[i].veh_keys.push(key),
+                true => stages[i].ped_keys.push(key),
+            }
+        }
+        stages.sort_by(|a, b| a.offset_secs.total_cmp(&b.offset_secs));
+
+        // lagging arrows run on after the rest of their stage clears
+        for signal in &veh_signals {
+            if signal.turn_mode != settings::TurnMode::LaggingProtectedPermissive {
+                continue;
+            }
+            let key = [signal.src_dir, signal.dst_dir];
+            if let Some(stage) = stages.iter_mut().find(|v| v.veh_keys.contains(&key)) {
+                stage.lag_keys.push(key);
+                stage.lag_secs = f64::max(stage.lag_secs, signal.protected_secs);
+            }
+        }
+
+        let n = stages.len();
+        Self {
+            stages,
+            stage: 0,
+            interval: Interval::Green,
+            interval_secs: 0.0,
+            gap_secs: 0.0,
+            veh_calls: vec![false; n],
+            ped_calls: vec![false; n],
+            walk_secs: None,
+            lpi_secs: 0.0,
+            red_secs: vec![0.0; n],
+            ped_red_secs: vec![0.0; n],
+        }
+    }
+
+    pub fn call_ped(&mut self, key: [settings::Dir; 2]) {
+        if let Some(i) = self.stages.iter().position(|v| v.ped_keys.contains(&key)) {
+            self.ped_calls[i] = true;
+        }
+    }
+
+    // occupancy of the approach detectors per stage
+    pub fn detector_occupancy(
+        &self,
+        settings: &settings::Settings,
+        agents: &[forward::Agent],
+    ) -> Vec<bool> {
+        let mut occupied = vec![false; self.stages.len()];
+        for agent in agents {
+            let Some(motion) = &agent.motion else {
+                continue;
+            };
+            let Some(stop_distance) = motion.approach.as_ref().and_then(|v| v.stop_distance) else {
+                continue;
+            };
+            let flow = settings.veh_flow(agent.kind, agent.flow_index).unwrap();
+            let key = [flow.src_dir, flow.dst_dir];
+            let Some(i) = self.stages.iter().position(|v| v.veh_keys.contains(&key)) else {
+                continue;
+            };
+
+            let d1 = stop_distance - settings.actuated.detector_setback;
+            let d0 = d1 - settings.actuated.detector_length;
+            let front = motion.distance;
+            let rear = motion.distance - interaction::VEH_LENGTH;
+            if rear <= d1 && d0 <= front {
+                occupied[i] = true;
+            }
+        }
+        occupied
+    }
+
+    pub fn update(&mut self, params: &settings::ActuatedControl, occupied: &[bool], dt: f64) {
+        if self.stages.is_empty() {
+            return;
+        }
+
+        // detector calls on stages not being served
+        for (i, &occupied) in occupied.iter().enumerate() {
+            if occupied && !(i == self.stage && self.interval == Interval::Green) {
+                self.veh_calls[i] = true;
+            }
+        }
+
+        self.interval_secs += dt;
+        for i in 0..self.stages.len() {
+            let leading = self.interval == Interval::Green && self.interval_secs < self.lpi_secs;
+            if i == self.stage && self.interval != Interval::AllRed && !leading {
+                self.red_secs[i] = 0.0;
+            } else {
+                self.red_secs[i] += dt;
+            }
+            let walking = i == self.stage
+                && self
+                    .walk_secs
+                    .is_some_and(|v| self.interval_secs - v < params.walk_secs + params.blink_secs);
+            if walking {
+                self.ped_red_secs[i] = 0.0;
+            } else {
+                self.ped_red_secs[i] += dt;
+            }
+        }
+
+        match self.interval {
+            Interval::Green => {
+                if occupied.get(self.stage).copied().unwrap_or(false) {
+                    self.gap_secs = 0.0;
+                } else {
+                    self.gap_secs += dt;
+                }
+
+                // push-button during the green starts the walk
+                if self.walk_secs.is_none() && self.ped_calls[self.stage] {
+                    self.walk_secs = Some(self.interval_secs);
+                    self.ped_calls[self.stage] = false;
+                }
+
+                let mut min_green_secs = self.lpi_secs + params.min_green_secs;
+                if let Some(walk_secs) = self.walk_secs {
+                    let ped_secs = walk_secs + params.walk_secs + params.blink_secs;
+                    min_green_secs = f64::max(min_green_secs, ped_secs);
+                }
+                let conflicting = (0..self.stages.len())
+                    .any(|i| i != self.stage && (self.veh_calls[i] || self.ped_calls[i]));
+                let gap_out = self.gap_secs >= params.passage_secs;
+                let max_out = self.interval_secs >= self.lpi_secs + params.max_green_secs;
+
+                if self.interval_secs >= min_green_secs && conflicting && (gap_out || max_out) {
+                    self.interval = match self.stages[self.stage].lag_keys.is_empty() {
+                        true => Interval::Yellow,
+                        false => Interval::LagArrow,
+                    };
+                    self.interval_secs = 0.0;
+                    self.walk_secs = None;
+                }
+            }
+            Interval::LagArrow => {
+                let lag_secs = params.yellow_secs + self.stages[self.stage].lag_secs;
+                if self.interval_secs >= lag_secs {
+                    self.interval = Interval::Yellow;
+                    self.interval_secs = 0.0;
+                }
+            }
+            Interval::Yellow => {
+                if self.interval_secs >= params.yellow_secs {
+                    self.interval = Interval::AllRed;
+                    self.interval_secs = 0.0;
+                }
+            }
+            Interval::AllRed => {
+                if self.interval_secs >= params.all_red_secs {
+                    let n = self.stages.len();
+                    let next = (1..=n)
+                        .map(|v| (self.stage + v) % n)
+                        .find(|&i| self.veh_calls[i] || self.ped_calls[i])
+                        .unwrap_or((self.stage + 1) % n);
+
+                    self.stage = next;
+                    self.interval = Interval::Green;
+                    self.interval_secs = 0.0;
+                    self.gap_secs = 0.0;
+                    self.veh_calls[next] = false;
+                    self.lpi_secs = 0.0;
+                    if self.ped_calls[next] {
+                        // leading pedestrian interval before the vehicle green
+                        self.walk_secs = Some(0.0);
+                        self.lpi_secs = params.lpi_secs;
+                        self.ped_calls[next] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn veh_state(
+        &self,
+        params: &settings::ActuatedControl,
+        key: [settings::Dir; 2],
+    ) -> Option<(forward::VehSignalState, f64)> {
+        let i = self.stages.iter().position(|v| v.veh_keys.contains(&key))?;
+        let lag_keys = &self.stages[i].lag_keys;
+        let lagging = lag_keys.contains(&key);
+        let state = match self.interval {
+            Interval::Green if i == self.stage && self.interval_secs >= self.lpi_secs => (
+                forward::VehSignalState::Green,
+                self.interval_secs - self.lpi_secs,
+            ),
+            // the rest of the stage clears before the lagging arrow
+            Interval::LagArrow if i == self.stage && self.interval_secs < params.yellow_secs => {
+                match lagging {
+                    true => (forward::VehSignalState::Green, self.interval_secs),
+                    false => (forward::VehSignalState::Yellow, self.interval_secs),
+                }
+            }
+            Interval::LagArrow if i == self.stage => match lagging {
+                true => (
+                    forward::VehSignalState::GreenArrow,
+                    self.interval_secs - params.yellow_secs,
+                ),
+                false => (
+                    forward::VehSignalState::Red,
+                    self.interval_secs - params.yellow_secs,
+                ),
+            },
+            Interval::Yellow if i == self.stage && (lag_keys.is_empty() || lagging) => {
+                (forward::VehSignalState::Yellow, self.interval_secs)
+            }
+            Interval::Yellow if i == self.stage => (
+                forward::VehSignalState::Red,
+                self.stages[i].lag_secs + self.interval_secs,
+            ),
+            _ => (forward::VehSignalState::Red, self.red_secs[i]),
+        };
+        Some(state)
+    }
+
+    pub fn ped_state(
+        &self,
+        params: &settings::ActuatedControl,
+        key: [settings::Dir; 2],
+    ) -> Option<(forward::PedSignalState, f64)> {
+        let i = self.stages.iter().position(|v| v.ped_keys.contains(&key))?;
+        let walk_secs = self.walk_secs.filter(|_| i == self.stage);
+        let state = match walk_secs.map(|v| self.interval_secs - v) {
+            Some(secs) if secs < params.walk_secs => (forward::PedSignalState::Green, secs),
+            Some(secs) if secs < params.walk_secs + params.blink_secs => {
+                (forward::PedSignalState::Blink, secs - params.walk_secs)
+            }
+            Some(secs) => (
+                forward::PedSignalState::Red,
+                secs - params.walk_secs - params.blink_secs,
+            ),
+            None => (forward::PedSignalState::Red, self.ped_red_secs[i]),
+        };
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use settings::Dir;
+
+    const A: [Dir; 2] = [Dir::NxPy, Dir::PxPy];
+    const B: [Dir; 2] = [Dir::NyNx, Dir::PyNx];
+    const C: [Dir; 2] = [Dir::PxNy, Dir::NxNy];
+    const P: [Dir; 2] = [Dir::NxNy, Dir::NxPy];
+    const Q: [Dir; 2] = [Dir::PyNx, Dir::PyPx];
+
+    // stage 0 runs A, C and the crosswalk P, stage 1 runs B and the crosswalk Q
+    fn controller(turn_mode: settings::TurnMode) -> Controller {
+        let veh = |[src_dir, dst_dir]: [Dir; 2], offset_secs, turn_mode| settings::VehSignal {
+            src_dir,
+            dst_dir,
+            offset_secs,
+            turn_mode,
+            protected_secs: 5.0,
+            ..Default::default()
+        };
+        let ped = |[src_dir, dst_dir]: [Dir; 2], offset_secs| settings::PedSignal {
+            src_dir,
+            dst_dir,
+            offset_secs,
+            ..Default::default()
+        };
+        let settings = settings::Settings {
+            veh_signals: vec![
+                veh(A, 0.0, turn_mode),
+                veh(B, 60.0, settings::TurnMode::Permissive),
+                veh(C, 0.0, settings::TurnMode::Permissive),
+            ],
+            ped_signals: vec![ped(P, 0.0), ped(Q, 60.0)],
+            signal_plan: None,
+            ..Default::default()
+        };
+        Controller::new(&settings)
+    }
+
+    // steps of an exact binary fraction so interval times land on the thresholds
+    fn run(
+        controller: &mut Controller,
+        params: &settings::ActuatedControl,
+        occupied: [bool; 2],
+        secs: f64,
+    ) {
+        for _ in 0..(secs * 8.0).round() as usize {
+            controller.update(params, &occupied, 0.125);
+        }
+    }
+
+    fn at(controller: &Controller) -> (usize, Interval, f64) {
+        (
+            controller.stage,
+            controller.interval,
+            controller.interval_secs,
+        )
+    }
+
+    #[test]
+    fn stages_from_offsets() {
+        let controller = controller(settings::TurnMode::Permissive);
+        assert_eq!(controller.stages.len(), 2);
+        assert_eq!(controller.stages[0].veh_keys, [A, C]);
+        assert_eq!(controller.stages[0].ped_keys, [P]);
+        assert_eq!(controller.stages[1].veh_keys, [B]);
+        assert_eq!(controller.stages[1].ped_keys, [Q]);
+    }
+
+    #[test]
+    fn holds_min_green() {
+        let params = settings::ActuatedControl::default();
+        let mut controller = controller(settings::TurnMode::Permissive);
+
+        // no traffic on the green, a call waiting on the other stage
+        run(&mut controller, &params, [false, true], 9.875);
+        assert_eq!(at(&controller), (0, Interval::Green, 9.875));
+        run(&mut controller, &params, [false, true], 0.125);
+        assert_eq!(at(&controller), (0, Interval::Yellow, 0.0));
+    }
+
+    #[test]
+    fn gaps_out_then_clears() {
+        let params = settings::ActuatedControl::default();
+        let mut controller = controller(settings::TurnMode::Permissive);
+
+        run(&mut controller, &params, [true, true], 15.0);
+        run(&mut controller, &params, [false, true], 2.875);
+        assert_eq!(at(&controller), (0, Interval::Green, 17.875));
+        run(&mut controller, &params, [false, true], 0.125);
+        assert_eq!(at(&controller), (0, Interval::Yellow, 0.0));
+
+        run(&mut controller, &params, [false, true], 3.0);
+        assert_eq!(at(&controller), (0, Interval::AllRed, 0.0));
+        assert_eq!(
+            controller.veh_state(&params, A),
+            Some((forward::VehSignalState::Red, 0.0))
+        );
+        run(&mut controller, &params, [false, true], 2.0);
+        assert_eq!(at(&controller), (1, Interval::Green, 0.0));
+        assert_eq!(
+            controller.veh_state(&params, B),
+            Some((forward::VehSignalState::Green, 0.0))
+        );
+        assert_eq!(
+            controller.veh_state(&params, A),
+            Some((forward::VehSignalState::Red, 2.0))
+        );
+    }
+
+    #[test]
+    fn maxes_out_only_on_a_call() {
+        let params = settings::ActuatedControl::default();
+        let mut controller = controller(settings::TurnMode::Permissive);
+
+        // rests in green without a conflicting call
+        run(&mut controller, &params, [true, false], 100.0);
+        assert_eq!(at(&controller), (0, Interval::Green, 100.0));
+
+        let mut controller = self::controller(settings::TurnMode::Permissive);
+        run(&mut controller, &params, [true, true], 59.875);
+        assert_eq!(at(&controller), (0, Interval::Green, 59.875));
+        run(&mut controller, &params, [true, true], 0.125);
+        assert_eq!(at(&controller), (0, Interval::Yellow, 0.0));
+    }
+
+    #[test]
+    fn push_button_extends_green_for_the_walk() {
+        let params = settings::ActuatedControl::default();
+        let mut controller = controller(settings::TurnMode::Permissive);
+
+        run(&mut controller, &params, [false, true], 5.0);
+        controller.call_ped(P);
+        run(&mut controller, &params, [false, true], 0.125);
+        assert_eq!(controller.walk_secs, Some(5.125));
+        assert_eq!(
+            controller.ped_state(&params, P).unwrap().0,
+            forward::PedSignalState::Green
+        );
+
+        run(&mut controller, &params, [false, true], 7.0);
+        assert_eq!(
+            controller.ped_state(&params, P),
+            Some((forward::PedSignalState::Blink, 0.0))
+        );
+
+        // the green holds until the blink ends
+        run(&mut controller, &params, [false, true], 9.875);
+        assert_eq!(at(&controller), (0, Interval::Green, 22.0));
+        run(&mut controller, &params, [false, true], 0.125);
+        assert_eq!(at(&controller), (0, Interval::Yellow, 0.0));
+        assert_eq!(controller.walk_secs, None);
+    }
+
+    #[test]
+    fn push_button_calls_its_stage() {
+        let params = settings::ActuatedControl::default();
+        let mut controller = controller(settings::TurnMode::Permissive);
+
+        // a pedestrian call alone ends the green
+        controller.call_ped(Q);
+        assert_eq!(controller.ped_calls, [false, true]);
+        run(&mut controller, &params, [false, false], 15.0);
+        assert_eq!(at(&controller), (1, Interval::Green, 0.0));
+        assert_eq!(
+            controller.ped_state(&params, Q),
+            Some((forward::PedSignalState::Green, 0.0))
+        );
+        assert_eq!(controller.ped_calls, [false, false]);
+    }
+
+    #[test]
+    fn leading_pedestrian_interval() {
+        let params = settings::ActuatedControl {
+            lpi_secs: 2.5,
+            ..Default::default()
+        };
+        let mut controller = controller(settings::TurnMode::Permissive);
+
+        controller.call_ped(Q);
+        run(&mut controller, &params, [false, false], 15.0);
+        assert_eq!(at(&controller), (1, Interval::Green, 0.0));
+
+        // the walk leads the vehicle green
+        run(&mut controller, &params, [false, false], 2.375);
+        assert_eq!(
+            controller.ped_state(&params, Q),
+            Some((forward::PedSignalState::Green, 2.375))
+        );
+        assert_eq!(
+            controller.veh_state(&params, B).unwrap().0,
+            forward::VehSignalState::Red
+        );
+        run(&mut controller, &params, [false, false], 0.125);
+        assert_eq!(
+            controller.veh_state(&params, B),
+            Some((forward::VehSignalState::Green, 0.0))
+        );
+
+        // the min green runs from the end of the interval
+        let mut controller = self::controller(settings::TurnMode::Permissive);
+        controller.call_ped(Q);
+        run(&mut controller, &params, [false, false], 15.0);
+        controller.walk_secs = None;
+        run(&mut controller, &params, [true, false], 12.375);
+        assert_eq!(at(&controller), (1, Interval::Green, 12.375));
+        run(&mut controller, &params, [true, false], 0.125);
+        assert_eq!(at(&controller), (1, Interval::Yellow, 0.0));
+    }
+
+    #[test]
+    fn lagging_arrow_after_the_stage_clears() {
+        let params = settings::ActuatedControl::default();
+        let mut controller = controller(settings::TurnMode::LaggingProtectedPermissive);
+        assert_eq!(controller.stages[0].lag_keys, [A]);
+
+        run(&mut controller, &params, [false, true], 10.0);
+        assert_eq!(at(&controller), (0, Interval::LagArrow, 0.0));
+
+        // the rest of the stage clears while the turn keeps its green
+        run(&mut controller, &params, [false, true], 1.0);
+        assert_eq!(
+            controller.veh_state(&params, A),
+            Some((forward::VehSignalState::Green, 1.0))
+        );
+        assert_eq!(
+            controller.veh_state(&params, C),
+            Some((forward::VehSignalState::Yellow, 1.0))
+        );
+        run(&mut controller, &params, [false, true], 3.0);
+        assert_eq!(
+            controller.veh_state(&params, A),
+            Some((forward::VehSignalState::GreenArrow, 1.0))
+        );
+        assert_eq!(
+            controller.veh_state(&params, C),
+            Some((forward::VehSignalState::Red, 1.0))
+        );
+
+        // then the arrow turns yellow
+        run(&mut controller, &params, [false, true], 4.0);
+        assert_eq!(at(&controller), (0, Interval::Yellow, 0.0));
+        assert_eq!(
+            controller.veh_state(&params, A),
+            Some((forward::VehSignalState::Yellow, 0.0))
+        );
+        assert_eq!(
+            controller.veh_state(&params, C),
+            Some((forward::VehSignalState::Red, 5.0))
+        );
+    }
+}
@@ -0,0 +1,693 @@
+use crate::{model, settings};
+
+pub const MAX_ITER: usize = 1000;
+pub const GRAD_TOL: f64 = 1e-6;
+pub const FEASIBLE_MARGIN: f64 = 1e-2;
+
+// observed features of a turning vehicle
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Observation {
+    pub kind: String,
+    pub v_in: f64,
+    pub v_out: f64,
+    pub padding_out: f64,
+    pub angle: f64,
+    pub radius: f64,
+    pub large: f64,
+    pub hn_in: f64,
+    pub hn_out: f64,
+    pub c_in: f64,
+    pub c_out: f64,
+    pub v_min: f64,
+    pub x_min: f64,
+    pub r_min: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Family {
+    Gamma,
+    Normal,
+    Weibull,
+}
+
+// one observed response with the regressors of both distribution parameters
+pub struct Sample {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub z: f64,
+}
+
+pub fn read_features(path: &std::path::Path) -> std::io::Result<Vec<Observation>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = text.lines().filter(|v| !v.trim().is_empty());
+    let header: Vec<_> = lines
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|v| *v == name);
+
+    let required = [
+        "v_in",
+        "v_out",
+        "padding_out",
+        "angle",
+        "radius",
+        "large",
+        "c_in",
+        "c_out",
+        "v_min",
+        "x_min",
+        "r_min",
+    ];
+    for name in required {
+        if column(name).is_none() {
+            return Err(invalid_data(format!("missing column {name}")));
+        }
+    }
+
+    if column("kind").is_none() {
+        log::warn!("no kind column, every observation is taken as lt_veh");
+    }
+
+    let mut observations = vec![];
+    for (i, line) in lines.enumerate() {
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let value = |name: &str| -> std::io::Result<f64> {
+            let Some(field) = column(name).and_then(|j| fields.get(j)) else {
+                return Ok(f64::NAN);
+            };
+            match *field {
+                "true" => Ok(1.0),
+                "false" => Ok(0.0),
+                "" => Ok(f64::NAN),
+                _ => field
+                    .parse()
+                    .map_err(|_| invalid_data(format!("line {}: bad {name} {field}", i + 2))),
+            }
+        };
+        let kind = column("kind").and_then(|j| fields.get(j)).copied();
+        observations.push(Observation {
+            kind: kind.unwrap_or("lt_veh").to_string(),
+            v_in: value("v_in")?,
+            v_out: value("v_out")?,
+            padding_out: value("padding_out")?,
+            angle: value("angle")?,
+            radius: value("radius")?,
+            large: value("large")?,
+            hn_in: value("hn_in")?,
+            hn_out: value("hn_out")?,
+            c_in: value("c_in")?,
+            c_out: value("c_out")?,
+            v_min: value("v_min")?,
+            x_min: value("x_min")?,
+            r_min: value("r_min")?,
+        });
+    }
+
+    // right-turn regressors need both hn columns, without them every row would drop
+    let missing: Vec<_> = ["hn_in", "hn_out"]
+        .into_iter()
+        .filter(|v| column(v).is_none())
+        .collect();
+    if !missing.is_empty() && observations.iter().any(|v| v.kind == "rt_veh") {
+        let missing = missing.join(", ");
+        return Err(invalid_data(format!(
+            "missing column {missing} for rt_veh observations"
+        )));
+    }
+    Ok(observations)
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    // lanczos approximation, g = 7
+    const C: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = (1..9).fold(C[0], |acc, i| acc + C[i] / (x + i as f64));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// negative log-likelihood, infinite outside the parameter space
+pub fn neg_log_likelihood(family: Family, a: &[f64], b: &[f64], samples: &[Sample]) -> f64 {
+    let dot = |c: &[f64], x: &[f64]| c.iter().zip(x).map(|(c, x)| c * x).sum::<f64>();
+    let mut nll = 0.0;
+    for sample in samples {
+        let p1 = dot(a, &sample.x);
+        let p2 = dot(b, &sample.y);
+        let z = sample.z;
+        nll += match family {
+            Family::Gamma if p1 > 0.0 && p2 > 0.0 => {
+                ln_gamma(p1) + p1 * p2.ln() - (p1 - 1.0) * z.ln() + z / p2
+            }
+            Family::Normal if p2 > 0.0 => {
+                p2.ln()
+                    + 0.5 * (2.0 * std::f64::consts::PI).ln()
+                    + (z - p1).powi(2) / (2.0 * p2 * p2)
+            }
+            Family::Weibull if p1 > 0.0 && p2 > 0.0 => {
+                -p1.ln() + p1 * p2.ln() - (p1 - 1.0) * z.ln() + (z / p2).powf(p1)
+            }
+            _ => return f64::INFINITY,
+        };
+    }
+    match nll.is_finite() {
+        true => nll,
+        false => f64::INFINITY,
+    }
+}
+
+// squared violation of positive parameters, to find a feasible start
+pub fn infeasibility(family: Family, a: &[f64], b: &[f64], samples: &[Sample]) -> f64 {
+    let dot = |c: &[f64], x: &[f64]| c.iter().zip(x).map(|(c, x)| c * x).sum::<f64>();
+    let violation = |p: f64| (FEASIBLE_MARGIN - p).max(0.0).powi(2);
+    samples
+        .iter()
+        .map(|v| match family {
+            Family::Normal => violation(dot(b, &v.y)),
+            Family::Gamma | Family::Weibull => violation(dot(a, &v.x)) + violation(dot(b, &v.y)),
+        })
+        .sum()
+}
+
+fn gradient(
+    f: &impl Fn(&nalgebra::DVector<f64>) -> f64,
+    x: &nalgebra::DVector<f64>,
+) -> nalgebra::DVector<f64> {
+    let mut g = nalgebra::DVector::zeros(x.len());
+    for i in 0..x.len() {
+        let h = 1e-6 * (1.0 + x[i].abs());
+        let mut x1 = x.clone();
+        let mut x0 = x.clone();
+        x1[i] += h;
+        x0[i] -= h;
+        g[i] = (f(&x1) - f(&x0)) / (2.0 * h);
+    }
+    g
+}
+
+fn hessian(
+    f: &impl Fn(&nalgebra::DVector<f64>) -> f64,
+    x: &nalgebra::DVector<f64>,
+) -> nalgebra::DMatrix<f64> {
+    let n = x.len();
+    let mut hess = nalgebra::DMatrix::zeros(n, n);
+    for i in 0..n {
+        let h = 1e-4 * (1.0 + x[i].abs());
+        let mut x1 = x.clone();
+        let mut x0 = x.clone();
+        x1[i] += h;
+        x0[i] -= h;
+        let column = (gradient(f, &x1) - gradient(f, &x0)) / (2.0 * h);
+        hess.set_column(i, &column);
+    }
+    (&hess + hess.transpose()) * 0.5
+}
+
+// quasi-newton minimization with a backtracking line search
+pub fn bfgs(
+    f: &impl Fn(&nalgebra::DVector<f64>) -> f64,
+    x0: nalgebra::DVector<f64>,
+) -> Option<nalgebra::DVector<f64>> {
+    let n = x0.len();
+    let mut x = x0;
+    let mut fx = f(&x);
+    if !fx.is_finite() {
+        return None;
+    }
+    let mut g = gradient(f, &x);
+    let mut h_inv = nalgebra::DMatrix::identity(n, n);
+
+    for _ in 0..MAX_ITER {
+        if g.norm() < GRAD_TOL {
+            break;
+        }
+        let mut p = -(&h_inv * &g);
+        if p.dot(&g) >= 0.0 {
+            // not a descent direction, restart from steepest descent
+            h_inv = nalgebra::DMatrix::identity(n, n);
+            p = -g.clone();
+        }
+
+        let mut t = 1.0;
+        let slope = p.dot(&g);
+        let (x_next, f_next) = loop {
+            let x_next = &x + &p * t;
+            let f_next = f(&x_next);
+            if f_next <= fx + 1e-4 * t * slope {
+                break (x_next, f_next);
+            }
+            t *= 0.5;
+            if t < 1e-16 {
+                return Some(x);
+            }
+        };
+
+        let g_next = gradient(f, &x_next);
+        let s = &x_next - &x;
+        let y = &g_next - &g;
+        let sy = s.dot(&y);
+        if sy > 1e-12 {
+            let rho = 1.0 / sy;
+            let identity = nalgebra::DMatrix::<f64>::identity(n, n);
+            let left = &identity - &s * y.transpose() * rho;
+            let right = &identity - &y * s.transpose() * rho;
+            h_inv = left * h_inv * right + &s * s.transpose() * rho;
+        }
+
+        let converged = (fx - f_next).abs() <= 1e-12 * (1.0 + fx.abs());
+        x = x_next;
+        fx = f_next;
+        g = g_next;
+        if converged {
+            break;
+        }
+    }
+    Some(x)
+}
+
+// fits the non-zero coefficients of both parameters, zeros stay excluded
+pub fn fit(
+    family: Family,
+    a: &model::Coef,
+    b: &model::Coef,
+    samples: &[Sample],
+) -> Option<(model::Coef, model::Coef, f64)> {
+    let start: Vec<_> = a.coef.iter().chain(&b.coef).copied().collect();
+    let free: Vec<_> = start
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| **v != 0.0)
+        .map(|(i, _)| i)
+        .collect();
+    if samples.len() <= free.len() {
+        return None;
+    }
+
+    let n_a = a.coef.len();
+    let full = |x: &nalgebra::DVector<f64>| {
+        let mut coef = start.clone();
+        for (k, &i) in free.iter().enumerate() {
+            coef[i] = x[k];
+        }
+        coef
+    };
+    let f = |x: &nalgebra::DVector<f64>| {
+        let coef = full(x);
+        neg_log_likelihood(family, &coef[..n_a], &coef[n_a..], samples)
+    };
+
+    let x0 = nalgebra::DVector::from_iterator(free.len(), free.iter().map(|&i| start[i]));
+    let x0 = match f(&x0).is_finite() {
+        true => x0,
+        false => {
+            // base coefficients outside the parameter space for these observations
+            let g = |x: &nalgebra::DVector<f64>| {
+                let coef = full(x);
+                infeasibility(family, &coef[..n_a], &coef[n_a..], samples)
+            };
+            bfgs(&g, x0)?
+        }
+    };
+    let x = bfgs(&f, x0)?;
+    let nll = f(&x);
+
+    // standard errors from the inverse of the observed information
+    let cov = hessian(&f, &x).try_inverse();
+    let mut std_err = vec![0.0; n_a + b.coef.len()];
+    for (k, &i) in free.iter().enumerate() {
+        std_err[i] = match &cov {
+            Some(cov) if cov[(k, k)] > 0.0 => cov[(k, k)].sqrt(),
+            _ => f64::NAN,
+        };
+    }
+
+    let coef = full(&x);
+    let a = model::Coef {
+        coef: coef[..n_a].to_vec(),
+        std_err: std_err[..n_a].to_vec(),
+    };
+    let b = model::Coef {
+        coef: coef[n_a..].to_vec(),
+        std_err: std_err[n_a..].to_vec(),
+    };
+    Some((a, b, -nll))
+}
+
+fn samples(
+    observations: &[&Observation],
+    response: fn(&Observation) -> f64,
+    x: fn(&Observation) -> Vec<f64>,
+    y: fn(&Observation) -> Vec<f64>,
+) -> Vec<Sample> {
+    observations
+        .iter()
+        .map(|v| Sample {
+            x: x(v),
+            y: y(v),
+            z: response(v),
+        })
+        .collect()
+}
+
+// regressors follow compute_lt_veh
+fn lt_veh_samples(observations: &[&Observation]) -> Vec<(&'static str, Family, Vec<Sample>)> {
+    let c_x = |v: &Observation| vec![1.0, v.v_in, v.angle, v.radius, v.padding_out, v.v_out];
+    let c_y = |v: &Observation| vec![1.0, v.v_in, v.radius, v.padding_out, v.v_out];
+    let min_x = |v: &Observation| vec![1.0, v.v_in, v.radius, v.angle, v.padding_out, v.large];
+    let min_y = |v: &Observation| vec![1.0, v.radius, v.padding_out];
+    let r_x = |v: &Observation| vec![v.angle, v.radius, v.padding_out, 1.0];
+    vec![
+        (
+            "c_in",
+            Family::Gamma,
+            samples(observations, |v| v.c_in, c_x, c_y),
+        ),
+        (
+            "c_out",
+            Family::Gamma,
+            samples(observations, |v| v.c_out, c_x, c_y),
+        ),
+        (
+            "v_min",
+            Family::Normal,
+            samples(observations, |v| v.v_min, min_x, min_y),
+        ),
+        (
+            "x_min",
+            Family::Normal,
+            samples(observations, |v| v.x_min, min_x, min_y),
+        ),
+        (
+            "r_min",
+            Family::Normal,
+            samples(observations, |v| v.r_min, r_x, r_x),
+        ),
+    ]
+}
+
+// regressors follow compute_rt_veh
+fn rt_veh_samples(observations: &[&Observation]) -> Vec<(&'static str, Family, Vec<Sample>)> {
+    let c_x = |v: &Observation| vec![v.v_in, v.angle];
+    let c_y = |v: &Observation| vec![v.v_in, v.hn_out];
+    let min_x = |v: &Observation| vec![v.v_in, v.angle, v.hn_in];
+    let r_x = |v: &Observation| vec![v.angle, f64::min(v.hn_in, v.hn_out)];
+    let r_y = |v: &Observation| vec![v.angle, v.v_min];
+    vec![
+        (
+            "c_in",
+            Family::Gamma,
+            samples(observations, |v| v.c_in, c_x, c_y),
+        ),
+        (
+            "c_out",
+            Family::Gamma,
+            samples(observations, |v| v.c_out, c_x, c_y),
+        ),
+        (
+            "v_min",
+            Family::Normal,
+            samples(observations, |v| v.v_min, min_x, min_x),
+        ),
+        (
+            "x_min",
+            Family::Normal,
+            samples(observations, |v| v.x_min, min_x, min_x),
+        ),
+        (
+            "r_min",
+            Family::Weibull,
+            samples(observations, |v| v.r_min, r_x, r_y),
+        ),
+    ]
+}
+
+fn fit_terms(
+    kind: &str,
+    terms: Vec<(&'static str, Family, Vec<Sample>)>,
+    mut coefs: Vec<(&mut model::Coef, &mut model::Coef)>,
+) {
+    for ((name, family, samples), (a, b)) in terms.into_iter().zip(&mut coefs) {
+        // positive responses only for gamma and weibull
+        let n = samples.len();
+        let samples: Vec<_> = samples
+            .into_iter()
+            .filter(|v| v.x.iter().chain(&v.y).all(|v| v.is_finite()) && v.z.is_finite())
+            .collect();
+        if samples.len() < n {
+            log::warn!(
+                "{kind}.{name}: dropped {} observations with missing values",
+                n - samples.len()
+            );
+        }
+        let samples: Vec<_> = samples
+            .into_iter()
+            .filter(|v| family == Family::Normal || v.z > 0.0)
+            .collect();
+        match fit(family, a, b, &samples) {
+            Some((a_fit, b_fit, log_likelihood)) => {
+                log::info!(
+                    "{kind}.{name}: {} observations, log-likelihood {log_likelihood:.3}",
+                    samples.len()
+                );
+                if a_fit
+                    .std_err
+                    .iter()
+                    .chain(&b_fit.std_err)
+                    .any(|v| v.is_nan())
+                {
+                    log::warn!("{kind}.{name}: standard errors unavailable at the boundary");
+                }
+                **a = a_fit;
+                **b = b_fit;
+            }
+            None => log::warn!(
+                "{kind}.{name}: {} observations, kept the base coefficients",
+                samples.len()
+            ),
+        }
+    }
+}
+
+pub fn run(
+    settings: &settings::Settings,
+    input: &std::path::Path,
+    output: &std::path::Path,
+    model_version: &str,
+    site: Option<&str>,
+) -> std::io::Result<()> {
+    let mut model = model::BehaviourModel::from_settings(settings)?;
+    let observations = read_features(input)?;
+
+    let lt: Vec<_> = observations.iter().filter(|v| v.kind == "lt_veh").collect();
+    let rt: Vec<_> = observations.iter().filter(|v| v.kind == "rt_veh").collect();
+    log::info!(
+        "read {} left-turn and {} right-turn observations from {}",
+        lt.len(),
+        rt.len(),
+        input.display()
+    );
+
+    if !lt.is_empty() {
+        let m = &mut model.lt_veh;
+        let coefs = vec![
+            (&mut m.c_in.shape, &mut m.c_in.scale),
+            (&mut m.c_out.shape, &mut m.c_out.scale),
+            (&mut m.v_min.mean, &mut m.v_min.std_dev),
+            (&mut m.x_min.mean, &mut m.x_min.std_dev),
+            (&mut m.r_min.mean, &mut m.r_min.std_dev),
+        ];
+        fit_terms("lt_veh", lt_veh_samples(&lt), coefs);
+    }
+    if !rt.is_empty() {
+        let m = &mut model.rt_veh;
+        let coefs = vec![
+            (&mut m.c_in.shape, &mut m.c_in.scale),
+            (&mut m.c_out.shape, &mut m.c_out.scale),
+            (&mut m.v_min.mean, &mut m.v_min.std_dev),
+            (&mut m.x_min.mean, &mut m.x_min.std_dev),
+            (&mut m.r_min.shape, &mut m.r_min.scale),
+        ];
+        fit_terms("rt_veh", rt_veh_samples(&rt), coefs);
+    }
+
+    model.version = model_version.to_string();
+    if let Some(site) = site {
+        model.site = site.to_string();
+    }
+    model.save(output)?;
+    log::info!("wrote model {} to {}", model.label(), output.display());
+    Ok(())
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn coef(coef: &[f64]) -> model::Coef {
+        model::Coef {
+            coef: coef.to_vec(),
+            std_err: vec![],
+        }
+    }
+
+    // responses drawn for a regressor uniform on 0..1
+    fn synthetic(draw: impl Fn(f64, &mut rand::rngs::StdRng) -> f64) -> Vec<Sample> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        (0..2000)
+            .map(|_| {
+                let u = rand::Rng::gen_range(&mut rng, 0.0..1.0);
+                let z = draw(u, &mut rng);
+                Sample {
+                    x: vec![1.0, u],
+                    y: vec![1.0, u],
+                    z,
+                }
+            })
+            .collect()
+    }
+
+    // within four standard errors that are themselves small, fixed zeros exactly
+    fn assert_close(coef: &model::Coef, expected: &[f64]) {
+        assert_eq!(coef.std_err.len(), expected.len());
+        for ((c, e), s) in coef.coef.iter().zip(expected).zip(&coef.std_err) {
+            assert!(
+                (c - e).abs() <= 4.0 * s && *s < 0.2,
+                "{coef:?} != {expected:?}"
+            );
+        }
+    }
+
+    fn features(text: &str) -> std::io::Result<Vec<Observation>> {
+        let path = std::env::temp_dir().join(format!("features-{}.csv", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let observations = read_features(&path);
+        std::fs::remove_file(&path).unwrap();
+        observations
+    }
+
+    #[test]
+    fn read_features_requires_hn_for_rt_veh() {
+        let header = "kind,v_in,v_out,padding_out,angle,radius,large,c_in,c_out,v_min,x_min,r_min";
+        let row = "0.1,0.2,0.3,0.4,0.5";
+        let lt = format!("{header}\nlt_veh,8,9,1,90,12,false,{row}\n");
+        assert_eq!(features(&lt).unwrap().len(), 1);
+
+        let rt = format!("{header}\nrt_veh,8,9,1,90,12,false,{row}\n");
+        let err = features(&rt).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("hn_in, hn_out"));
+
+        let rt = format!("{header},hn_in\nrt_veh,8,9,1,90,12,false,{row},0.2\n");
+        let err = features(&rt).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("missing column hn_out for rt_veh observations"));
+    }
+
+    #[test]
+    fn ln_gamma_known_values() {
+        let pi = std::f64::consts::PI;
+        assert!((ln_gamma(0.5) - pi.sqrt().ln()).abs() < 1e-12);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-12);
+        assert!(ln_gamma(1.0).abs() < 1e-12);
+        // reflection below 0.5, gamma(0.1) = 9.513507698668732
+        assert!((ln_gamma(0.1) - 9.513_507_698_668_732f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn neg_log_likelihood_closed_form() {
+        let samples = [Sample {
+            x: vec![1.0],
+            y: vec![1.0],
+            z: 1.0,
+        }];
+        let nll = |family| neg_log_likelihood(family, &[2.0], &[3.0], &samples);
+
+        let ln_2pi = (2.0 * std::f64::consts::PI).ln();
+        assert!((nll(Family::Normal) - (3f64.ln() + 0.5 * ln_2pi + 1.0 / 18.0)).abs() < 1e-12);
+        assert!((nll(Family::Gamma) - (2.0 * 3f64.ln() + 1.0 / 3.0)).abs() < 1e-12);
+        let weibull = -2f64.ln() + 2.0 * 3f64.ln() + 1.0 / 9.0;
+        assert!((nll(Family::Weibull) - weibull).abs() < 1e-12);
+
+        // outside the parameter space
+        let nll = neg_log_likelihood(Family::Normal, &[2.0], &[-1.0], &samples);
+        assert_eq!(nll, f64::INFINITY);
+        let nll = neg_log_likelihood(Family::Gamma, &[0.0], &[3.0], &samples);
+        assert_eq!(nll, f64::INFINITY);
+    }
+
+    #[test]
+    fn fits_normal_mean_and_std_dev() {
+        let samples = synthetic(|u, rng| {
+            let distr = rand_distr::Normal::new(2.0 + 0.5 * u, 0.2 + 0.3 * u).unwrap();
+            rand::Rng::sample(rng, distr)
+        });
+        let (a, b, _) = fit(
+            Family::Normal,
+            &coef(&[1.0, 1.0]),
+            &coef(&[1.0, 1.0]),
+            &samples,
+        )
+        .unwrap();
+        assert_close(&a, &[2.0, 0.5]);
+        assert_close(&b, &[0.2, 0.3]);
+        assert!(a.std_err.iter().chain(&b.std_err).all(|v| *v > 0.0));
+    }
+
+    #[test]
+    fn fits_gamma_shape_and_scale() {
+        let samples = synthetic(|u, rng| {
+            let distr = rand_distr::Gamma::new(3.0, 1.0 + 0.5 * u).unwrap();
+            rand::Rng::sample(rng, distr)
+        });
+        // zero coefficients stay fixed
+        let (a, b, _) = fit(
+            Family::Gamma,
+            &coef(&[1.0, 0.0]),
+            &coef(&[1.0, 1.0]),
+            &samples,
+        )
+        .unwrap();
+        assert_close(&a, &[3.0, 0.0]);
+        assert_eq!((a.coef[1], a.std_err[1]), (0.0, 0.0));
+        assert_close(&b, &[1.0, 0.5]);
+    }
+
+    #[test]
+    fn fits_weibull_shape_and_scale() {
+        let samples = synthetic(|u, rng| {
+            let distr = rand_distr::Weibull::new(2.0 + u, 2.0).unwrap();
+            rand::Rng::sample(rng, distr)
+        });
+        let (a, b, _) = fit(
+            Family::Weibull,
+            &coef(&[1.0, 0.0]),
+            &coef(&[1.0, 1.0]),
+            &samples,
+        )
+        .unwrap();
+        assert_close(&a, &[2.0, 0.0]);
+        assert_close(&b, &[2.0, 1.0]);
+    }
+}
@@ -0,0 +1,502 @@
+use std::io::Read;
+
+use crate::{gps, report, settings};
+
+pub const FPS: f64 = 30.0;
+pub const HAMPEL_HALF_WINDOW: usize = 15;
+pub const HAMPEL_SIGMAS: f64 = 3.0;
+// positions averaged over one second of frames
+pub const SMOOTH_HALF_WINDOW: usize = 15;
+
+// registered image of a colmap reconstruction, world to camera pose
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub id: u32,
+    pub name: String,
+    pub rotation: nalgebra::UnitQuaternion<f64>,
+    pub translation: nalgebra::Vector3<f64>,
+}
+
+impl Image {
+    // projection centre
+    pub fn center(&self) -> nalgebra::Vector3<f64> {
+        -(self.rotation.inverse() * self.translation)
+    }
+
+    // camera y axis points down in the image
+    pub fn up(&self) -> nalgebra::Vector3<f64> {
+        -(self.rotation.inverse() * nalgebra::Vector3::y())
+    }
+
+    // frame number of the extracted image, e.g. 0001.png
+    pub fn frame(&self) -> Option<usize> {
+        let stem = std::path::Path::new(&self.name).file_stem()?.to_str()?;
+        let digits: String = stem.chars().filter(|v| v.is_ascii_digit()).collect();
+        digits.parse().ok()
+    }
+}
+
+fn pose(q: [f64; 4], t: [f64; 3]) -> (nalgebra::UnitQuaternion<f64>, nalgebra::Vector3<f64>) {
+    let [qw, qx, qy, qz] = q;
+    let rotation =
+        nalgebra::UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(qw, qx, qy, qz));
+    (rotation, nalgebra::Vector3::from(t))
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+pub fn read_images_txt(path: &std::path::Path) -> std::io::Result<Vec<Image>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = text.lines().filter(|v| !v.starts_with('#'));
+
+    let mut images = vec![];
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // IMAGE_ID QW QX QY QZ TX TY TZ CAMERA_ID NAME, then a line of 2d points
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let bad = || invalid_data(format!("{}: bad image {line}", path.display()));
+        if fields.len() < 10 {
+            return Err(bad());
+        }
+        let values: Vec<f64> = fields[1..8]
+            .iter()
+            .map(|v| v.parse().map_err(|_| bad()))
+            .collect::<std::io::Result<_>>()?;
+        let (rotation, translation) = pose(
+            [values[0], values[1], values[2], values[3]],
+            [values[4], values[5], values[6]],
+        );
+        images.push(Image {
+            id: fields[0].parse().map_err(|_| bad())?,
+            name: fields[9..].join(" "),
+            rotation,
+            translation,
+        });
+        lines.next();
+    }
+    Ok(images)
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64(reader: &mut impl Read) -> std::io::Result<f64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+pub fn read_images_bin(path: &std::path::Path) -> std::io::Result<Vec<Image>> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(file);
+
+    let n = read_u64(&mut reader)?;
+    let mut images = vec![];
+    for _ in 0..n {
+        let id = read_u32(&mut reader)?;
+        let q = [0; 4].map(|_| read_f64(&mut reader));
+        let t = [0; 3].map(|_| read_f64(&mut reader));
+        let _camera_id = read_u32(&mut reader)?;
+        let mut name = vec![];
+        loop {
+            let mut byte = [0; 1];
+            reader.read_exact(&mut byte)?;
+            if byte[0] == 0 {
+                break;
+            }
+            name.push(byte[0]);
+        }
+        // x, y and point3D_id of every 2d point
+        let points = read_u64(&mut reader)?;
+        std::io::copy(&mut (&mut reader).take(points * 24), &mut std::io::sink())?;
+
+        let [qw, qx, qy, qz] = q;
+        let [tx, ty, tz] = t;
+        let (rotation, translation) = pose([qw?, qx?, qy?, qz?], [tx?, ty?, tz?]);
+        images.push(Image {
+            id,
+            name: String::from_utf8_lossy(&name).to_string(),
+            rotation,
+            translation,
+        });
+    }
+    Ok(images)
+}
+
+// images.bin or images.txt of a model directory
+pub fn read_images(model_dir: &std::path::Path) -> std::io::Result<Vec<Image>> {
+    let bin = model_dir.join("images.bin");
+    let txt = model_dir.join("images.txt");
+    if bin.exists() {
+        read_images_bin(&bin)
+    } else if txt.exists() {
+        read_images_txt(&txt)
+    } else {
+        let msg = format!("{}: no images.bin or images.txt", model_dir.display());
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, msg))
+    }
+}
+
+// principal plane of the centres, viewed from above the cameras
+pub fn project(
+    centers: &[nalgebra::Vector3<f64>],
+    up: nalgebra::Vector3<f64>,
+) -> Vec<nalgebra::Vector2<f64>> {
+    let n = centers.len() as f64;
+    let mean = centers.iter().sum::<nalgebra::Vector3<f64>>() / n;
+    let cov = centers
+        .iter()
+        .map(|v| (v - mean) * (v - mean).transpose())
+        .sum::<nalgebra::Matrix3<f64>>()
+        / n;
+    let eigen = cov.symmetric_eigen();
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+    let e1 = eigen.eigenvectors.column(order[0]).into_owned();
+    let mut e2 = eigen.eigenvectors.column(order[1]).into_owned();
+    if e1.cross(&e2).dot(&up) < 0.0 {
+        e2 = -e2;
+    }
+    centers
+        .iter()
+        .map(|v| nalgebra::Vector2::new((v - mean).dot(&e1), (v - mean).dot(&e2)))
+        .collect()
+}
+
+// similarity transform from src to dst points (umeyama), without reflection
+pub fn umeyama(
+    src: &[nalgebra::Vector2<f64>],
+    dst: &[nalgebra::Vector2<f64>],
+) -> Option<(f64, nalgebra::Matrix2<f64>, nalgebra::Vector2<f64>)> {
+    let n = src.len() as f64;
+    let mean_src = src.iter().sum::<nalgebra::Vector2<f64>>() / n;
+    let mean_dst = dst.iter().sum::<nalgebra::Vector2<f64>>() / n;
+    let var_src = src
+        .iter()
+        .map(|v| (v - mean_src).norm_squared())
+        .sum::<f64>()
+        / n;
+    if var_src <= 0.0 {
+        return None;
+    }
+    let cov = src
+        .iter()
+        .zip(dst)
+        .map(|(a, b)| (b - mean_dst) * (a - mean_src).transpose())
+        .sum::<nalgebra::Matrix2<f64>>()
+        / n;
+    let svd = cov.svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut s = nalgebra::Matrix2::identity();
+    if u.determinant() * v_t.determinant() < 0.0 {
+        s[(1, 1)] = -1.0;
+    }
+    let rotation = u * s * v_t;
+    let scale = (nalgebra::Matrix2::from_diagonal(&svd.singular_values) * s).trace() / var_src;
+    let translation = mean_dst - rotation * mean_src * scale;
+    Some((scale, rotation, translation))
+}
+
+// drops samples further than a few scaled deviations from the local median
+pub fn hampel_filter(values: &[f64], half_window: usize, sigmas: f64) -> Vec<bool> {
+    let median = |values: &mut Vec<f64>| {
+        values.sort_by(f64::total_cmp);
+        report::quantile(values, 0.5)
+    };
+    (0..values.len())
+        .map(|i| {
+            let (j, k) = (
+                i.saturating_sub(half_window),
+                usize::min(i + half_window + 1, values.len()),
+            );
+            let mut window = values[j..k].to_vec();
+            let m = median(&mut window);
+            let mut deviations: Vec<_> = values[j..k].iter().map(|v| (v - m).abs()).collect();
+            let mad = 1.4826 * median(&mut deviations);
+            (values[i] - m).abs() <= sigmas * mad.max(1e-9 * m.abs())
+        })
+        .collect()
+}
+
+fn smooth(p: &[nalgebra::Vector2<f64>], half_window: usize) -> Vec<nalgebra::Vector2<f64>> {
+    (0..p.len())
+        .map(|i| {
+            // symmetric window, shrinking towards the ends
+            let h = half_window.min(i).min(p.len() - 1 - i);
+            let (j, k) = (i - h, i + h + 1);
+            p[j..k].iter().sum::<nalgebra::Vector2<f64>>() / (k - j) as f64
+        })
+        .collect()
+}
+
+// linear interpolation over unregistered and dropped frames
+fn fill_gaps(
+    frames: &[usize],
+    p: &[nalgebra::Vector2<f64>],
+) -> (Vec<usize>, Vec<nalgebra::Vector2<f64>>) {
+    let (mut f, mut q) = (vec![], vec![]);
+    for i in 0..frames.len() {
+        if i > 0 {
+            let gap = frames[i] - frames[i - 1];
+            for k in 1..gap {
+                let u = k as f64 / gap as f64;
+                f.push(frames[i - 1] + k);
+                q.push(p[i - 1].lerp(&p[i], u));
+            }
+        }
+        f.push(frames[i]);
+        q.push(p[i]);
+    }
+    (f, q)
+}
+
+// frame and local coordinates of known points along the drive
+pub fn read_anchors(
+    path: &std::path::Path,
+) -> std::io::Result<Vec<(usize, nalgebra::Vector2<f64>)>> {
+    let table = report::Table::read(path)?;
+    table.require(&["frame", "x", "y"])?;
+    let mut anchors = vec![];
+    for row in &table.rows {
+        let frame = table.value(row, "frame");
+        let (Some(frame), Some(x), Some(y)) = (frame, table.value(row, "x"), table.value(row, "y"))
+        else {
+            continue;
+        };
+        anchors.push((frame as usize, nalgebra::Vector2::new(x, y)));
+    }
+    Ok(anchors)
+}
+
+// images in frame order, numbered by name or else by registration order,
+// a model mixing both has no consistent frame spacing
+fn frames(images: &mut Vec<Image>) -> Result<Vec<usize>, String> {
+    let unnumbered: Vec<_> = images.iter().filter(|v| v.frame().is_none()).collect();
+    if !unnumbered.is_empty() && unnumbered.len() < images.len() {
+        return Err(format!(
+            "{} of {} images without a frame number, e.g. {}",
+            unnumbered.len(),
+            images.len(),
+            unnumbered[0].name
+        ));
+    }
+    images.sort_by_key(|v| (v.frame(), v.id));
+    images.dedup_by(|a, b| a.frame().is_some() && a.frame() == b.frame());
+    let frames = images
+        .iter()
+        .enumerate()
+        .map(|(i, v)| v.frame().unwrap_or(i + 1))
+        .collect();
+    Ok(frames)
+}
+
+pub fn import(
+    model_dir: &std::path::Path,
+    scale: f64,
+    fps: f64,
+) -> std::io::Result<Option<gps::Run>> {
+    let mut images = read_images(model_dir)?;
+    let frames = frames(&mut images)
+        .map_err(|err| invalid_data(format!("{}: {err}", model_dir.display())))?;
+
+    let centers: Vec<_> = images.iter().map(|v| v.center()).collect();
+    let up = images
+        .iter()
+        .map(|v| v.up())
+        .sum::<nalgebra::Vector3<f64>>();
+    let track = project(&centers, up);
+
+    // scale and orientation from anchors, otherwise the given scale
+    let anchors_path = model_dir.join("anchors.csv");
+    let track: Vec<_> = match anchors_path.exists() {
+        true => {
+            let anchors = read_anchors(&anchors_path)?;
+            let pairs: Vec<_> = anchors
+                .iter()
+                .filter_map(|(frame, p)| Some((track[frames.iter().position(|v| v == frame)?], *p)))
+                .collect();
+            if pairs.len() < 2 {
+                let msg = format!(
+                    "{}: at least two anchors on registered frames",
+                    anchors_path.display()
+                );
+                return Err(invalid_data(msg));
+            }
+            let (src, dst): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+            let (s, r, t) =
+                umeyama(&src, &dst).ok_or_else(|| invalid_data("degenerate anchors".into()))?;
+            let residual = src
+                .iter()
+                .zip(&dst)
+                .map(|(a, b)| (r * a * s + t - b).norm())
+                .fold(0.0, f64::max);
+            log::info!(
+                "{}: aligned with scale {s:.4}, residual {residual:.3} m",
+                model_dir.display()
+            );
+            track.iter().map(|v| r * v * s + t).collect()
+        }
+        false => {
+            log::warn!(
+                "{}: no anchors, principal axes with scale {scale}",
+                model_dir.display()
+            );
+            track.iter().map(|v| v * scale).collect()
+        }
+    };
+
+    // jumps between consecutive frames are reconstruction failures,
+    // repeated as a jump only flags the frame after it
+    let (mut f, mut p) = (frames.clone(), track.clone());
+    loop {
+        let steps: Vec<_> = (0..p.len())
+            .map(|i| match i {
+                0 => 0.0,
+                _ => (p[i] - p[i - 1]).norm() / (f[i] - f[i - 1]) as f64,
+            })
+            .collect();
+        let keep = hampel_filter(&steps, HAMPEL_HALF_WINDOW, HAMPEL_SIGMAS);
+        if keep.iter().skip(1).all(|v| *v) {
+            break;
+        }
+        (f, p) = (0..p.len())
+            .filter(|&i| keep[i] || i == 0)
+            .map(|i| (f[i], p[i]))
+            .unzip();
+    }
+    let dropped = track.len() - f.len();
+    if dropped > 0 {
+        log::info!("{}: dropped {dropped} outlier frames", model_dir.display());
+    }
+
+    // the moving average assumes evenly spaced frames
+    let (f, p) = fill_gaps(&f, &p);
+    let t: Vec<_> = f.iter().map(|v| *v as f64 / fps).collect();
+
+    let file = model_dir.display().to_string();
+    Ok(gps::Run::from_track(
+        file,
+        &t,
+        &smooth(&p, SMOOTH_HALF_WINDOW),
+    ))
+}
+
+pub fn run(
+    settings: &settings::Settings,
+    paths: &[std::path::PathBuf],
+    kind: &str,
+    scale: f64,
+    fps: f64,
+    large: bool,
+    output_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    let mut runs = vec![];
+    for path in paths {
+        match import(path, scale, fps)? {
+            Some(run) => runs.push(run),
+            None => log::warn!("{}: too few registered images for a run", path.display()),
+        }
+    }
+
+    std::fs::create_dir_all(output_dir)?;
+    let path = output_dir.join("runs.csv");
+    gps::write_runs(settings, &runs, kind, large, &path)?;
+    gps::write_profiles(&runs, kind, &output_dir.join("profiles.csv"))?;
+
+    log::info!(
+        "imported {} runs from {} models into {}",
+        runs.len(),
+        paths.len(),
+        output_dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::{Matrix2, Rotation2, Vector2};
+
+    fn image(id: u32, name: &str) -> Image {
+        Image {
+            id,
+            name: name.to_string(),
+            rotation: Default::default(),
+            translation: Default::default(),
+        }
+    }
+
+    #[test]
+    fn frames_sorted_and_deduplicated() {
+        let mut images = vec![
+            image(1, "0003.png"),
+            image(2, "0001.png"),
+            image(3, "0003.png"),
+            image(4, "0002.png"),
+        ];
+        assert_eq!(frames(&mut images), Ok(vec![1, 2, 3]));
+        let ids: Vec<_> = images.iter().map(|v| v.id).collect();
+        assert_eq!(ids, vec![2, 4, 1]);
+
+        let mut images = vec![image(2, "b.png"), image(1, "a.png")];
+        assert_eq!(frames(&mut images), Ok(vec![1, 2]));
+        assert_eq!(images[0].id, 1);
+    }
+
+    #[test]
+    fn frames_reject_mixed_names() {
+        let mut images = vec![image(1, "0001.png"), image(2, "key.png")];
+        let err = frames(&mut images).unwrap_err();
+        assert!(err.contains("key.png"));
+    }
+
+    #[test]
+    fn umeyama_recovers_similarity() {
+        let rotation = *Rotation2::new(30f64.to_radians()).matrix();
+        let translation = Vector2::new(3.0, -4.0);
+        let src = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(1.0, 0.0),
+            Vector2::new(0.0, 2.0),
+            Vector2::new(-1.5, 0.5),
+        ];
+        let dst: Vec<_> = src
+            .iter()
+            .map(|v| rotation * v * 2.5 + translation)
+            .collect();
+
+        let (s, r, t) = umeyama(&src, &dst).unwrap();
+        assert!((s - 2.5).abs() < 1e-9);
+        assert!((r - rotation).norm() < 1e-9);
+        assert!((t - translation).norm() < 1e-9);
+    }
+
+    #[test]
+    fn umeyama_never_reflects() {
+        let src = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(1.0, 0.0),
+            Vector2::new(0.0, 1.0),
+        ];
+        let reflection = Matrix2::new(1.0, 0.0, 0.0, -1.0);
+        let dst: Vec<_> = src.iter().map(|v| reflection * v).collect();
+
+        let (_, r, _) = umeyama(&src, &dst).unwrap();
+        assert!((r.determinant() - 1.0).abs() < 1e-9);
+        assert_eq!(umeyama(&[Vector2::new(1.0, 1.0); 3], &dst), None);
+    }
+}
@@ -1,5 +1,6 @@
 use nalgebra::*;
 
+use crate::model;
 use crate::settings::*;
 
 pub fn intersection_point(p0: [f64; 2], p1: [f64; 2], q0: [f64; 2], q1: [f64; 2]) -> [f64; 2] {
@@ -17,8 +18,10 @@ pub fn intersection_point(p0: [f64; 2], p1: [f64; 2], q0: [f64; 2], q1: [f64; 2]
 pub const ROAD_LENGTH: f64 = 64.0;
 pub const STEP: f64 = 0.01;
 pub const MAX_TIME: f64 = 100.0;
+// the speed scale grows linearly with the elapsed green, hold it beyond the observed starts
+pub const A_GREEN_MAX: f64 = 10.0;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct VehOutput {
     pub c_in: f64,
     pub c_out: f64,
@@ -35,9 +38,12 @@ pub struct VehOutput {
     pub trajectory_series: Vec<[f64; 2]>,
 }
 
-pub fn compute_lt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput> {
-    let rng = &mut rand::thread_rng();
-
+pub fn compute_lt_veh(
+    settings: &Settings,
+    model: &model::LtVehModel,
+    flow: &VehFlow,
+    rng: &mut impl rand::Rng,
+) -> Option<VehOutput> {
     let mut velocity_series = vec![];
 
     let m = Rotation2::new(settings.angle.to_radians());
@@ -110,32 +116,26 @@ pub fn compute_lt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     };
 
     // c_in parameter
-    let a = vector![2.09, 0.256, -0.0155, 0.0, -0.168, 0.0];
-    let x = vector![1.0, v_in, angle, radius, padding_out, v_out];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.0573, -0.00173, -0.00109, 0.00219, 0.0];
-    let y = vector![1.0, v_in, radius, padding_out, v_out];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [1.0, v_in, angle, radius, padding_out, v_out];
+    let shape = model.c_in.shape.dot(&x).max(f64::EPSILON);
+    let y = [1.0, v_in, radius, padding_out, v_out];
+    let scale = model.c_in.scale.dot(&y).max(f64::EPSILON);
     let c_in = rand_distr::Gamma::new(shape, scale).unwrap();
     let c_in = rand::Rng::sample(rng, c_in);
 
     // c_out parameter
-    let a = vector![1.40, 0.0, 0.0, 0.0, 0.0633, -0.0224];
-    let x = vector![1.0, v_in, angle, radius, padding_out, v_out];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.0772, 0.0, 0.0, 0.0, -0.00355];
-    let y = vector![1.0, v_in, radius, padding_out, v_out];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [1.0, v_in, angle, radius, padding_out, v_out];
+    let shape = model.c_out.shape.dot(&x).max(f64::EPSILON);
+    let y = [1.0, v_in, radius, padding_out, v_out];
+    let scale = model.c_out.scale.dot(&y).max(f64::EPSILON);
     let c_out = rand_distr::Gamma::new(shape, scale).unwrap();
     let c_out = rand::Rng::sample(rng, c_out);
 
     // v_min parameter
-    let a = vector![-0.301, 0.0908, 0.0607, 0.0387, 0.233, -0.496];
-    let x = vector![1.0, v_in, radius, angle, padding_out, large_dummy];
-    let mean = a.dot(&x);
-    let b = vector![0.665, 0.0, 0.0419];
-    let y = vector![1.0, radius, padding_out];
-    let std_dev = b.dot(&y).max(f64::EPSILON);
+    let x = [1.0, v_in, radius, angle, padding_out, large_dummy];
+    let mean = model.v_min.mean.dot(&x);
+    let y = [1.0, radius, padding_out];
+    let std_dev = model.v_min.std_dev.dot(&y).max(f64::EPSILON);
     let v_min = rand_distr::Normal::new(mean, std_dev).unwrap();
     let v_min = rand::Rng::sample(rng, v_min);
 
@@ -174,12 +174,10 @@ pub fn compute_lt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     let max_step = velocity_series.len();
 
     // x_min parameter
-    let a = vector![1.42, 0.0, 0.586, 0.0896, 0.577, 0.0];
-    let x = vector![1.0, v_in, radius, angle, padding_out, large_dummy];
-    let mean = a.dot(&x);
-    let b = vector![0.135, 0.144, 0.336];
-    let y = vector![1.0, radius, padding_out];
-    let std_dev = b.dot(&y).max(f64::EPSILON);
+    let x = [1.0, v_in, radius, angle, padding_out, large_dummy];
+    let mean = model.x_min.mean.dot(&x);
+    let y = [1.0, radius, padding_out];
+    let std_dev = model.x_min.std_dev.dot(&y).max(f64::EPSILON);
     let x_min = rand_distr::Normal::new(mean, std_dev).unwrap();
     let x_min = rand::Rng::sample(rng, x_min);
 
@@ -202,22 +200,18 @@ pub fn compute_lt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     let x_o = x_min_ - x_min;
 
     // r_min parameter
-    let a = vector![0.127, 0.390, 0.862, -6.46];
-    let x = vector![angle, radius, padding_out, 1.0,];
-    let mean = a.dot(&x);
-    let b = vector![0.0363, 0.0624, 0.118, -2.86];
-    let y = vector![angle, radius, padding_out, 1.0,];
-    let std_dev = b.dot(&y).max(f64::EPSILON);
+    let x = [angle, radius, padding_out, 1.0];
+    let mean = model.r_min.mean.dot(&x);
+    let y = [angle, radius, padding_out, 1.0];
+    let std_dev = model.r_min.std_dev.dot(&y).max(f64::EPSILON);
     let r_min = rand_distr::Normal::new(mean, std_dev).unwrap();
     let r_min = rand::Rng::sample(rng, r_min);
 
     // curvature
-    let a = vector![-1.65, 0.0404, 0.334, 0.0, 0.461, 0.369];
-    let x = vector![1.0, angle, radius, large_dummy, padding_out, v_min];
-    let a1 = a.dot(&x);
-    let a = vector![2.33, 0.0, 0.335, 2.05, 1.04, 0.268];
-    let x = vector![1.0, angle, radius, large_dummy, padding_out, v_min];
-    let a2 = a.dot(&x);
+    let x = [1.0, angle, radius, large_dummy, padding_out, v_min];
+    let a1 = model.clothoid.a1.dot(&x);
+    let x = [1.0, angle, radius, large_dummy, padding_out, v_min];
+    let a2 = model.clothoid.a2.dot(&x);
     let l_clothoid1 = r_min.recip() / a1.powi(2).recip();
     let angle_clothoid1 = 0.5 * a1.powi(2).recip() * l_clothoid1.powi(2);
     let l_clothoid2 = r_min.recip() / a2.powi(2).recip();
@@ -316,9 +310,12 @@ pub fn compute_lt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     })
 }
 
-pub fn compute_rt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput> {
-    let rng = &mut rand::thread_rng();
-
+pub fn compute_rt_veh(
+    settings: &Settings,
+    model: &model::RtVehModel,
+    flow: &VehFlow,
+    rng: &mut impl rand::Rng,
+) -> Option<VehOutput> {
     let mut velocity_series = vec![];
 
     let m = Rotation2::new(settings.angle.to_radians());
@@ -393,32 +390,26 @@ pub fn compute_rt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     };
 
     // c_in parameter
-    let a = vector![0.320, -0.0150];
-    let x = vector![v_in, angle];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.000334, 0.0];
-    let y = vector![v_in, hn_out];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [v_in, angle];
+    let shape = model.c_in.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_in, hn_out];
+    let scale = model.c_in.scale.dot(&y).max(f64::EPSILON);
     let c_in = rand_distr::Gamma::new(shape, scale).unwrap();
     let c_in = rand::Rng::sample(rng, c_in);
 
     // c_out parameter
-    let a = vector![0.0275, 0.0108];
-    let x = vector![v_in, angle];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.000228, 0.00222];
-    let y = vector![v_in, hn_out];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [v_in, angle];
+    let shape = model.c_out.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_in, hn_out];
+    let scale = model.c_out.scale.dot(&y).max(f64::EPSILON);
     let c_out = rand_distr::Gamma::new(shape, scale).unwrap();
     let c_out = rand::Rng::sample(rng, c_out);
 
     // v_min parameter
-    let a = vector![0.488, 0.0236, 0.0325];
-    let x = vector![v_in, angle, hn_in];
-    let mean = a.dot(&x);
-    let b = vector![0.0261, 0.00689, 0.0];
-    let y = vector![v_in, angle, hn_in];
-    let std_dev = b.dot(&y).max(f64::EPSILON);
+    let x = [v_in, angle, hn_in];
+    let mean = model.v_min.mean.dot(&x);
+    let y = [v_in, angle, hn_in];
+    let std_dev = model.v_min.std_dev.dot(&y).max(f64::EPSILON);
     let v_min = rand_distr::Normal::new(mean, std_dev).unwrap();
     let v_min = rand::Rng::sample(rng, v_min);
 
@@ -457,12 +448,10 @@ pub fn compute_rt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     let max_step = velocity_series.len();
 
     // x_min parameter
-    let a = vector![0.917, 0.150, 0.218];
-    let x = vector![v_in, angle, hn_in];
-    let mean = a.dot(&x);
-    let b = vector![-0.438, 0.0975, 0.101];
-    let y = vector![v_in, angle, hn_in];
-    let std_dev = b.dot(&y).max(f64::EPSILON);
+    let x = [v_in, angle, hn_in];
+    let mean = model.x_min.mean.dot(&x);
+    let y = [v_in, angle, hn_in];
+    let std_dev = model.x_min.std_dev.dot(&y).max(f64::EPSILON);
     let x_min = rand_distr::Normal::new(mean, std_dev).unwrap();
     let x_min = rand::Rng::sample(rng, x_min);
 
@@ -485,22 +474,18 @@ pub fn compute_rt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     let x_o = x_min_ - x_min;
 
     // dynamic r_min parameter
-    let a = vector![0.0282, 0.0807];
-    let x = vector![angle, f64::min(hn_in, hn_out)];
-    let shape = a.dot(&x);
-    let b = vector![0.162, 1.43];
-    let y = vector![angle, v_min];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [angle, f64::min(hn_in, hn_out)];
+    let shape = model.r_min.shape.dot(&x);
+    let y = [angle, v_min];
+    let scale = model.r_min.scale.dot(&y).max(f64::EPSILON);
     let r_min = rand_distr::Weibull::new(scale, shape).unwrap();
     let r_min = rand::Rng::sample(rng, r_min);
 
     // curvature
-    let a = vector![6.09, 0.985, 0.186, 0.235, 0.0];
-    let x = vector![1.0, v_min, r_min, hn_in, hn_out];
-    let a1 = a.dot(&x);
-    let a = vector![6.81, 0.611, 0.313, 0.0, 0.188];
-    let x = vector![1.0, v_min, r_min, hn_in, hn_out];
-    let a2 = a.dot(&x);
+    let x = [1.0, v_min, r_min, hn_in, hn_out];
+    let a1 = model.clothoid.a1.dot(&x);
+    let x = [1.0, v_min, r_min, hn_in, hn_out];
+    let a2 = model.clothoid.a2.dot(&x);
     let l_clothoid1 = r_min.recip() / a1.powi(2).recip();
     let angle_clothoid1 = 0.5 * a1.powi(2).recip() * l_clothoid1.powi(2);
     let l_clothoid2 = r_min.recip() / a2.powi(2).recip();
@@ -599,7 +584,175 @@ pub fn compute_rt_veh(settings: &Settings, flow: &VehFlow) -> Option<VehOutput>
     })
 }
 
-#[derive(Clone, Debug)]
+// straight run-up along the initial heading until the path starts ROAD_LENGTH from the centre
+pub fn extend_upstream(mut output: VehOutput) -> VehOutput {
+    let series = &output.trajectory_series;
+    if series.len() < 2 {
+        return output;
+    }
+    let [t0, v0] = output.velocity_series[0];
+    let [_, x0] = output.position_series[0];
+    let p0 = Point2::from(series[0]);
+    let length = ROAD_LENGTH - p0.coords.norm();
+    let Some(direction) = (Point2::from(series[1]) - p0).try_normalize(f64::EPSILON) else {
+        return output;
+    };
+    if v0 <= 0.0 || length <= 0.0 {
+        return output;
+    }
+
+    let n = (length / (v0 * STEP)).ceil() as usize;
+    let mut velocity_series = Vec::with_capacity(n);
+    let mut position_series = Vec::with_capacity(n);
+    let mut curvature_series = Vec::with_capacity(n);
+    let mut trajectory_series = Vec::with_capacity(n);
+    for i in (1..=n).rev() {
+        let dx = v0 * STEP * i as f64;
+        velocity_series.push([t0 - STEP * i as f64, v0]);
+        position_series.push([t0 - STEP * i as f64, x0 - dx]);
+        curvature_series.push([x0 - dx, 0.0]);
+        trajectory_series.push((p0 - direction * dx).into());
+    }
+
+    velocity_series.append(&mut output.velocity_series);
+    position_series.append(&mut output.position_series);
+    curvature_series.append(&mut output.curvature_series);
+    trajectory_series.append(&mut output.trajectory_series);
+
+    VehOutput {
+        max_step: output.max_step + n,
+        velocity_series,
+        position_series,
+        curvature_series,
+        trajectory_series,
+        ..output
+    }
+}
+
+// provisional through model: normal entry and exit speeds blended by a smoothstep
+// across the intersection, there is no calibrated through behaviour yet, so the
+// terms of the cubic profile are left unset (NaN) rather than filled with dummies
+pub fn compute_st_veh(
+    settings: &Settings,
+    flow: &VehFlow,
+    rng: &mut impl rand::Rng,
+) -> Option<VehOutput> {
+    let m = Rotation2::new(settings.angle.to_radians());
+
+    // only straight movements, a turning pair in st_veh_flows spawns nothing
+    let (y_in, y_out, sl_setback, angle, sign) = match (flow.src_dir, flow.dst_dir) {
+        (Dir::NxPy, Dir::PxPy) | (Dir::PxNy, Dir::NxNy) => (
+            settings.lane_along.get(flow.src_lane)?,
+            settings.lane_along.get(flow.dst_lane)?,
+            settings.sl_setback_along,
+            0.0,
+            if flow.src_dir == Dir::NxPy { 1.0 } else { -1.0 },
+        ),
+        (Dir::NyNx, Dir::PyNx) | (Dir::PyPx, Dir::NyPx) => (
+            settings.lane_across.get(flow.src_lane)?,
+            settings.lane_across.get(flow.dst_lane)?,
+            settings.sl_setback_across,
+            m.angle(),
+            if flow.src_dir == Dir::NyNx { 1.0 } else { -1.0 },
+        ),
+        _ => return None,
+    };
+    let (y_in, y_out) = (*y_in, *y_out);
+    let tx = Isometry2::new(Vector2::zeros(), angle);
+
+    let distr = rand_distr::Normal::new(flow.v_in_mean, flow.v_in_stdv).unwrap();
+    let v_in = rand::Rng::sample(rng, distr);
+
+    let distr = rand_distr::Normal::new(flow.v_out_mean, flow.v_out_stdv).unwrap();
+    let v_out = rand::Rng::sample(rng, distr);
+
+    if v_in <= 0.0 || v_out <= 0.0 {
+        return None;
+    }
+
+    // transition between stop lines
+    let transition = |x: f64| {
+        let u = ((x + sl_setback) / (2.0 * sl_setback)).clamp(0.0, 1.0);
+        u * u * (3.0 - 2.0 * u)
+    };
+
+    // velocity and position
+    let mut velocity_series = vec![];
+    let mut position_series = vec![];
+    let mut t = 0.0;
+    let mut x = -ROAD_LENGTH;
+    while x <= ROAD_LENGTH {
+        if t > MAX_TIME {
+            break;
+        }
+        let v = v_in + (v_out - v_in) * transition(x);
+        velocity_series.push([t, v]);
+        position_series.push([t, x]);
+        x += v * STEP;
+        t += STEP;
+    }
+
+    let max_step = velocity_series.len();
+
+    // curvature
+    let curvature_series = position_series.iter().map(|&[_, x]| [x, 0.0]).collect();
+
+    // trajectory
+    let trajectory_series = position_series
+        .iter()
+        .map(|&[_, x]| {
+            let y = y_in + (y_out - y_in) * transition(x);
+            (tx * Point2::new(sign * x, y)).into()
+        })
+        .collect();
+
+    // origin time
+    let mut t_o = 0.0;
+    for w in position_series.windows(2) {
+        let [t, x0] = w[0];
+        let [_, x1] = w[1];
+
+        if x0 <= 0.0 && 0.0 < x1 {
+            t_o = t;
+        }
+    }
+
+    // origin shift
+    for [t, _] in velocity_series.iter_mut() {
+        *t -= t_o;
+    }
+    for [t, _] in position_series.iter_mut() {
+        *t -= t_o;
+    }
+
+    Some(VehOutput {
+        c_in: f64::NAN,
+        c_out: f64::NAN,
+        v_min: f64::min(v_in, v_out),
+        x_min: f64::NAN,
+        t_min: f64::NAN,
+        t_exit: t,
+        t_o,
+        x_o: ROAD_LENGTH,
+        max_step,
+        velocity_series,
+        position_series,
+        curvature_series,
+        trajectory_series,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PedContext {
+    pub a_green: f64,
+    pub t_blink: f64,
+    pub t_remaining: Option<f64>,
+    pub lt_veh_flow: f64,
+    pub forward_ped_flow: f64,
+    pub backward_ped_flow: f64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct PedOutput {
     pub v_1: f64,
     pub v_2: f64,
@@ -610,13 +763,16 @@ pub struct PedOutput {
     pub trajectory_series: Vec<[f64; 2]>,
 }
 
-pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
-    let rng = &mut rand::thread_rng();
-
+pub fn compute_ped(
+    settings: &Settings,
+    model: &model::PedModel,
+    flow: &PedFlow,
+    context: &PedContext,
+    rng: &mut impl rand::Rng,
+) -> Option<PedOutput> {
     let m = Rotation2::new(settings.angle.to_radians());
 
-    // TODO: implement the following parameters
-    let a_green = 0.0;
+    let a_green = context.a_green.min(A_GREEN_MAX);
 
     let distr = rand_distr::Normal::new(flow.v_in_mean, flow.v_in_stdv).unwrap();
     let v_in = rand::Rng::sample(rng, distr);
@@ -668,10 +824,9 @@ pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
     let center_side = x_in < cw_width * 0.5;
     let center_side_dummy = if center_side { 1.0 } else { 0.0 };
 
-    // TODO: implement the following parameters
-    let lt_veh_flow = 0.0;
-    let forward_ped_flow = 0.0;
-    let backward_ped_flow = 0.0;
+    let lt_veh_flow = context.lt_veh_flow;
+    let forward_ped_flow = context.forward_ped_flow;
+    let backward_ped_flow = context.backward_ped_flow;
 
     let tx = match (flow.src, flow.dst) {
         (Dir::NyNx, Dir::NyPx) => {
@@ -726,28 +881,23 @@ pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
     };
 
     // first half velocity
-    let a = vector![7.47, 0.0, 0.720, 4.19, 1.93];
-    let x = vector![v_in, 0.0, width, far_side_dummy, 1.0];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.00391, 0.0, -0.00106, -0.00414, 0.00185, 0.0697];
-    let y = vector![v_in, 0.0, width, far_side_dummy, a_green, 1.0];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [v_in, 0.0, width, far_side_dummy, 1.0];
+    let shape = model.v_1.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_in, 0.0, width, far_side_dummy, a_green, 1.0];
+    let scale = model.v_1.scale.dot(&y).max(f64::EPSILON);
     let v_1 = rand_distr::Gamma::new(shape, scale).unwrap();
     let v_1 = rand::Rng::sample(rng, v_1);
 
     // last half velocity
-    let a = vector![0.0, -2.10, 0.695, 4.10, 22.8];
-    let x = vector![v_in, v_1, width, far_side_dummy, 1.0];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.0, 0.0199, -0.0006, -0.00159, 0.0, 0.0256];
-    let y = vector![v_in, v_1, width, far_side_dummy, a_green, 1.0];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [v_in, v_1, width, far_side_dummy, 1.0];
+    let shape = model.v_2.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_in, v_1, width, far_side_dummy, a_green, 1.0];
+    let scale = model.v_2.scale.dot(&y).max(f64::EPSILON);
     let v_2 = rand_distr::Gamma::new(shape, scale).unwrap();
     let v_2 = rand::Rng::sample(rng, v_2);
 
     // first x
-    let a = vector![0.210, -0.0200, -0.220, -1.03, -1.06, 0.100, -6.36, 0.0, 2.11];
-    let x = vector![
+    let x = [
         cw_width,
         cw_setback,
         far_side_dummy,
@@ -756,25 +906,23 @@ pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
         x_in,
         lt_veh_flow,
         forward_ped_flow,
-        1.0
+        1.0,
     ];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![-0.0400, 0.0, 0.0, 0.0, -0.660, 2.31];
-    let y = vector![
+    let shape = model.x_1.shape.dot(&x).max(f64::EPSILON);
+    let y = [
         width,
         cw_width,
         lt_veh_flow,
         forward_ped_flow,
         backward_ped_flow,
-        1.0
+        1.0,
     ];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let scale = model.x_1.scale.dot(&y).max(f64::EPSILON);
     let x_1 = rand_distr::Weibull::new(scale, shape).unwrap();
     let x_1 = rand::Rng::sample(rng, x_1).max(0.0).min(cw_width);
 
     // mid x
-    let a = vector![-0.540, 0.0, 0.0, -0.390, 0.440, 0.830, 0.110, 2.16, 3.51];
-    let x = vector![
+    let x = [
         cw_width,
         cw_setback,
         far_side_dummy,
@@ -783,25 +931,23 @@ pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
         x_1,
         lt_veh_flow,
         forward_ped_flow,
-        1.0
+        1.0,
     ];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.0, 1.13, 0.0, 0.0, -0.950, -1.86];
-    let y = vector![
+    let shape = model.x_2.shape.dot(&x).max(f64::EPSILON);
+    let y = [
         width,
         cw_width,
         lt_veh_flow,
         forward_ped_flow,
         backward_ped_flow,
-        1.0
+        1.0,
     ];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let scale = model.x_2.scale.dot(&y).max(f64::EPSILON);
     let x_2 = rand_distr::Weibull::new(scale, shape).unwrap();
     let x_2 = rand::Rng::sample(rng, x_2).max(0.0).min(cw_width);
 
     // last x
-    let a = vector![0.450, 0.0200, 0.150, -0.660, -0.220, 0.200, 0.0, 0.0, -1.19];
-    let x = vector![
+    let x = [
         cw_width,
         cw_setback,
         far_side_dummy,
@@ -810,19 +956,18 @@ pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
         x_2,
         lt_veh_flow,
         forward_ped_flow,
-        1.0
+        1.0,
     ];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = vector![0.0, 1.0, -10.5, 6.93, -1.69, -1.94];
-    let y = vector![
+    let shape = model.x_3.shape.dot(&x).max(f64::EPSILON);
+    let y = [
         width,
         cw_width,
         lt_veh_flow,
         forward_ped_flow,
         forward_ped_flow + backward_ped_flow,
-        1.0
+        1.0,
     ];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let scale = model.x_3.scale.dot(&y).max(f64::EPSILON);
     let x_3 = rand_distr::Weibull::new(scale, shape).unwrap();
     let x_3 = rand::Rng::sample(rng, x_3).max(0.0).min(cw_width);
 
@@ -857,13 +1002,16 @@ pub fn compute_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
     })
 }
 
-pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput> {
-    let rng = &mut rand::thread_rng();
-
+pub fn compute_ig_ped(
+    settings: &Settings,
+    model: &model::IgPedModel,
+    flow: &PedFlow,
+    context: &PedContext,
+    rng: &mut impl rand::Rng,
+) -> Option<PedOutput> {
     let m = Rotation2::new(settings.angle.to_radians());
 
-    // TODO: implement the following parameters
-    let t_blink = 0.0;
+    let t_blink = context.t_blink;
 
     let distr = rand_distr::Normal::new(flow.v_in_mean, flow.v_in_stdv).unwrap();
     let v_in = rand::Rng::sample(rng, distr);
@@ -918,10 +1066,9 @@ pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput>
     let center_side = x_in < cw_width * 0.5;
     let center_side_dummy = if center_side { 1.0 } else { 0.0 };
 
-    // TODO: implement the following parameters
-    let lt_veh_flow = 0.0;
-    let forward_ped_flow = 0.0;
-    let backward_ped_flow = 0.0;
+    let lt_veh_flow = context.lt_veh_flow;
+    let forward_ped_flow = context.forward_ped_flow;
+    let backward_ped_flow = context.backward_ped_flow;
 
     let tx = match (flow.src, flow.dst) {
         (Dir::NyNx, Dir::NyPx) => {
@@ -976,44 +1123,46 @@ pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput>
     };
 
     // contact velocity
-    let a = nalgebra::matrix![0.256, 24.1];
-    let x = nalgebra::matrix![d_in, 1.0];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = nalgebra::matrix![0.0379, 0.0218];
-    let y = nalgebra::matrix![v_in, 1.0];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let x = [d_in, 1.0];
+    let shape = model.v_0.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_in, 1.0];
+    let scale = model.v_0.scale.dot(&y).max(f64::EPSILON);
     let v_0 = rand_distr::Gamma::new(shape, scale).unwrap();
     let v_0 = rand::Rng::sample(rng, v_0);
 
     // first half velocity
-    let a = nalgebra::vector![3.88, 0.129, -3.51];
-    let x = nalgebra::vector![v_0, width, 1.0];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = nalgebra::vector![-0.0144, 0.0158, 0.170];
-    let y = nalgebra::vector![v_0, t_blink, 1.0];
-    let scale = b.dot(&y).max(f64::EPSILON);
-    let c = nalgebra::vector![-0.000055, 0.777];
-    let z = nalgebra::vector![forward_ped_flow + backward_ped_flow, 1.0];
-    let shift = c.dot(&z).max(f64::EPSILON);
+    let x = [v_0, width, 1.0];
+    let shape = model.v_1.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_0, t_blink, 1.0];
+    let scale = model.v_1.scale.dot(&y).max(f64::EPSILON);
+    let z = [forward_ped_flow + backward_ped_flow, 1.0];
+    let shift = model.v_1.shift.dot(&z).max(f64::EPSILON);
     let v_1 = rand_distr::Gamma::new(shape, scale).unwrap();
     let v_1 = rand::Rng::sample(rng, v_1) + shift;
 
     // last half velocity
-    let a = nalgebra::vector![0.580, 6.67];
-    let x = nalgebra::vector![v_1, 1.0];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = nalgebra::vector![0.0862, -0.00333];
-    let y = nalgebra::vector![v_1, 1.0];
-    let scale = b.dot(&y).max(f64::EPSILON);
-    let c = nalgebra::vector![0.218, -0.0597, 0.499];
-    let z = nalgebra::vector![v_1, far_side_dummy, 1.0];
-    let shift = c.dot(&z).max(f64::EPSILON);
+    let x = [v_1, 1.0];
+    let shape = model.v_2.shape.dot(&x).max(f64::EPSILON);
+    let y = [v_1, 1.0];
+    let scale = model.v_2.scale.dot(&y).max(f64::EPSILON);
+    let z = [v_1, far_side_dummy, 1.0];
+    let shift = model.v_2.shift.dot(&z).max(f64::EPSILON);
     let v_2 = rand_distr::Gamma::new(shape, scale).unwrap();
     let v_2 = rand::Rng::sample(rng, v_2) + shift;
 
+    // countdown at entry, hurry to clear before red
+    let (v_1, v_2) = match context.t_remaining {
+        Some(t_remaining) => {
+            let x = [width / t_remaining.max(f64::EPSILON), 1.0];
+            let countdown = &model.countdown;
+            let v_clear = countdown.v_clear.dot(&x).min(countdown.max_speed);
+            (v_1.max(v_clear), v_2.max(v_clear))
+        }
+        None => (v_1, v_2),
+    };
+
     // first x
-    let a = nalgebra::vector![0.210, -0.0200, -0.220, -1.03, -1.06, 0.100, -6.36, 0.0, 2.11];
-    let x = nalgebra::vector![
+    let x = [
         cw_width,
         cw_setback,
         far_side_dummy,
@@ -1022,25 +1171,23 @@ pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput>
         x_in,
         lt_veh_flow,
         forward_ped_flow,
-        1.0
+        1.0,
     ];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = nalgebra::vector![-0.0400, 0.0, 0.0, 0.0, -0.660, 2.31];
-    let y = nalgebra::vector![
+    let shape = model.x_1.shape.dot(&x).max(f64::EPSILON);
+    let y = [
         width,
         cw_width,
         lt_veh_flow,
         forward_ped_flow,
         backward_ped_flow,
-        1.0
+        1.0,
     ];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let scale = model.x_1.scale.dot(&y).max(f64::EPSILON);
     let x_1 = rand_distr::Weibull::new(scale, shape).unwrap();
     let x_1 = rand::Rng::sample(rng, x_1).max(0.0).min(cw_width);
 
     // mid x
-    let a = nalgebra::vector![-0.540, 0.0, 0.0, -0.390, 0.440, 0.830, 0.110, 2.16, 3.51];
-    let x = nalgebra::vector![
+    let x = [
         cw_width,
         cw_setback,
         far_side_dummy,
@@ -1049,25 +1196,23 @@ pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput>
         x_1,
         lt_veh_flow,
         forward_ped_flow,
-        1.0
+        1.0,
     ];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = nalgebra::vector![0.0, 1.13, 0.0, 0.0, -0.950, -1.86];
-    let y = nalgebra::vector![
+    let shape = model.x_2.shape.dot(&x).max(f64::EPSILON);
+    let y = [
         width,
         cw_width,
         lt_veh_flow,
         forward_ped_flow,
         backward_ped_flow,
-        1.0
+        1.0,
     ];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let scale = model.x_2.scale.dot(&y).max(f64::EPSILON);
     let x_2 = rand_distr::Weibull::new(scale, shape).unwrap();
     let x_2 = rand::Rng::sample(rng, x_2).max(0.0).min(cw_width);
 
     // last x
-    let a = nalgebra::vector![0.450, 0.0200, 0.150, -0.660, -0.220, 0.200, 0.0, 0.0, -1.19];
-    let x = nalgebra::vector![
+    let x = [
         cw_width,
         cw_setback,
         far_side_dummy,
@@ -1076,19 +1221,18 @@ pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput>
         x_2,
         lt_veh_flow,
         forward_ped_flow,
-        1.0
+        1.0,
     ];
-    let shape = a.dot(&x).max(f64::EPSILON);
-    let b = nalgebra::vector![0.0, 1.0, -10.5, 6.93, -1.69, -1.94];
-    let y = nalgebra::vector![
+    let shape = model.x_3.shape.dot(&x).max(f64::EPSILON);
+    let y = [
         width,
         cw_width,
         lt_veh_flow,
         forward_ped_flow,
         forward_ped_flow + backward_ped_flow,
-        1.0
+        1.0,
     ];
-    let scale = b.dot(&y).max(f64::EPSILON);
+    let scale = model.x_3.scale.dot(&y).max(f64::EPSILON);
     let x_3 = rand_distr::Weibull::new(scale, shape).unwrap();
     let x_3 = rand::Rng::sample(rng, x_3).max(0.0).min(cw_width);
 
@@ -1122,3 +1266,66 @@ pub fn compute_ig_ped(settings: &Settings, flow: &PedFlow) -> Option<PedOutput>
         trajectory_series,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // first half speeds of the default crossing, sampled from the same seed
+    fn ped_speeds(a_green: f64) -> Vec<f64> {
+        let settings = Settings::default();
+        let model = model::BehaviourModel::default();
+        let context = PedContext {
+            a_green,
+            ..Default::default()
+        };
+        let mut rng: rand::rngs::StdRng = rand::SeedableRng::seed_from_u64(1);
+        let flow = &settings.ped_flows[1];
+        std::iter::repeat_with(|| compute_ped(&settings, &model.ped, flow, &context, &mut rng))
+            .flatten()
+            .map(|v| v.v_1)
+            .take(1000)
+            .collect()
+    }
+
+    #[test]
+    fn ped_speed_late_in_a_long_green() {
+        let mut late = ped_speeds(100.0);
+        assert_eq!(late, ped_speeds(A_GREEN_MAX));
+        assert!(late.iter().sum::<f64>() / (late.len() as f64) < 2.0);
+
+        late.sort_by(f64::total_cmp);
+        assert!(late[late.len() * 99 / 100] < 3.0);
+    }
+
+    // both half speeds of inter-green crossings with the given remaining seconds
+    fn ig_ped_speeds(model: &model::IgPedModel, t_remaining: Option<f64>) -> Vec<[f64; 2]> {
+        let settings = Settings::default();
+        let context = PedContext {
+            t_remaining,
+            ..Default::default()
+        };
+        let mut rng: rand::rngs::StdRng = rand::SeedableRng::seed_from_u64(1);
+        let flow = &settings.ig_ped_flows[0];
+        std::iter::repeat_with(|| compute_ig_ped(&settings, model, flow, &context, &mut rng))
+            .flatten()
+            .map(|v| [v.v_1, v.v_2])
+            .take(100)
+            .collect()
+    }
+
+    #[test]
+    fn countdown_raises_to_capped_clearance_speed() {
+        let mut model = model::BehaviourModel::default().ig_ped;
+        let free = ig_ped_speeds(&model, None);
+        let hurried = ig_ped_speeds(&model, Some(0.1));
+        for ([v_1, v_2], [w_1, w_2]) in free.iter().zip(&hurried) {
+            assert_eq!(*w_1, v_1.max(model.countdown.max_speed));
+            assert_eq!(*w_2, v_2.max(model.countdown.max_speed));
+        }
+
+        model.countdown.max_speed = 4.0;
+        let hurried = ig_ped_speeds(&model, Some(0.1));
+        assert!(hurried.iter().flatten().all(|&v| v >= 4.0));
+    }
+}
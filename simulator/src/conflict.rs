@@ -0,0 +1,325 @@
+use nalgebra::*;
+
+use crate::forward;
+
+pub const MAX_TTC: f64 = 10.0;
+pub const WARNING_TTC: f64 = 3.0;
+
+const VEH_RADIUS: f64 = 1.5;
+const PED_RADIUS: f64 = 0.5;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PetEvent {
+    pub veh_id: usize,
+    pub veh_kind: forward::AgentKind,
+    pub veh_flow_index: usize,
+    pub ped_id: usize,
+    pub ped_kind: forward::AgentKind,
+    pub ped_flow_index: usize,
+    pub point: [f64; 2],
+    pub veh_secs: f64,
+    pub ped_secs: f64,
+    pub pet: f64,
+    pub veh_speed: f64,
+    pub ped_speed: f64,
+}
+
+pub fn compute_pet(a: &forward::Agent, b: &forward::Agent) -> Option<PetEvent> {
+    let (veh, ped) = match (&a.output, &b.output) {
+        (forward::AgentOutput::Veh(_), forward::AgentOutput::Ped(_)) => (a, b),
+        (forward::AgentOutput::Ped(_), forward::AgentOutput::Veh(_)) => (b, a),
+        _ => return None,
+    };
+
+    let veh_track = track(veh);
+    let ped_track = track(ped);
+
+    let mut event: Option<PetEvent> = None;
+    for w in veh_track.windows(2) {
+        let (s0, p0) = w[0];
+        let (s1, p1) = w[1];
+
+        for v in ped_track.windows(2) {
+            let (r0, q0) = v[0];
+            let (r1, q1) = v[1];
+
+            let Some((u, w)) = segment_intersection(p0, p1, q0, q1) else {
+                continue;
+            };
+
+            let veh_secs = s0 + u * (s1 - s0);
+            let ped_secs = r0 + w * (r1 - r0);
+            let pet = (veh_secs - ped_secs).abs();
+
+            // keep the closest crossing in time when paths meet more than once
+            if event.as_ref().is_some_and(|v| v.pet <= pet) {
+                continue;
+            }
+
+            event = Some(PetEvent {
+                veh_id: veh.id,
+                veh_kind: veh.kind,
+                veh_flow_index: veh.flow_index,
+                ped_id: ped.id,
+                ped_kind: ped.kind,
+                ped_flow_index: ped.flow_index,
+                point: (p0 + (p1 - p0) * u).into(),
+                veh_secs,
+                ped_secs,
+                pet,
+                veh_speed: (p1 - p0).norm() / (s1 - s0),
+                ped_speed: (q1 - q0).norm() / (r1 - r0),
+            });
+        }
+    }
+
+    event
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TtcRecord {
+    pub ids: [usize; 2],
+    pub kinds: [forward::AgentKind; 2],
+    pub flow_indices: [usize; 2],
+    pub min_ttc: f64,
+    pub secs: f64,
+    pub points: [[f64; 2]; 2],
+}
+
+pub fn compute_ttc(a: &forward::Agent, b: &forward::Agent) -> Option<f64> {
+    let dp = Point2::from(b.position()) - Point2::from(a.position());
+    let dv = Vector2::from(b.velocity()) - Vector2::from(a.velocity());
+    ttc(dp, dv, radius(a) + radius(b))
+}
+
+// earliest root of |dp + dv t| = r under constant velocities
+fn ttc(dp: Vector2<f64>, dv: Vector2<f64>, r: f64) -> Option<f64> {
+    if dp.norm() <= r {
+        return Some(0.0);
+    }
+
+    let qa = dv.dot(&dv);
+    let qb = 2.0 * dp.dot(&dv);
+    let qc = dp.dot(&dp) - r.powi(2);
+    if qa < f64::EPSILON {
+        return None;
+    }
+    let disc = qb.powi(2) - 4.0 * qa * qc;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = (-qb - disc.sqrt()) / (2.0 * qa);
+    if t > 0.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+pub fn update_ttc_records(
+    records: &mut ahash::AHashMap<[usize; 2], TtcRecord>,
+    agents: &[forward::Agent],
+    secs: f64,
+) {
+    let states: Vec<_> = agents
+        .iter()
+        .map(|v| {
+            (
+                Point2::from(v.position()),
+                Vector2::from(v.velocity()),
+                radius(v),
+            )
+        })
+        .collect();
+
+    for i in 0..agents.len() {
+        for j in i + 1..agents.len() {
+            let (a, b, sa, sb) = if agents[i].id < agents[j].id {
+                (&agents[i], &agents[j], states[i], states[j])
+            } else {
+                (&agents[j], &agents[i], states[j], states[i])
+            };
+
+            let Some(ttc) = ttc(sb.0 - sa.0, sb.1 - sa.1, sa.2 + sb.2) else {
+                continue;
+            };
+            if ttc > MAX_TTC {
+                continue;
+            }
+
+            let ids = [a.id, b.id];
+            if records.get(&ids).is_some_and(|v| v.min_ttc <= ttc) {
+                continue;
+            }
+            records.insert(
+                ids,
+                TtcRecord {
+                    ids,
+                    kinds: [a.kind, b.kind],
+                    flow_indices: [a.flow_index, b.flow_index],
+                    min_ttc: ttc,
+                    secs,
+                    points: [sa.0.into(), sb.0.into()],
+                },
+            );
+        }
+    }
+}
+
+fn radius(agent: &forward::Agent) -> f64 {
+    match agent.output {
+        forward::AgentOutput::Veh(_) => VEH_RADIUS,
+        forward::AgentOutput::Ped(_) => PED_RADIUS,
+    }
+}
+
+// recorded positions over time
+fn track(agent: &forward::Agent) -> Vec<(f64, Point2<f64>)> {
+    let series = agent.output.trajectory_series();
+    agent
+        .history
+        .iter()
+        .map(|&(secs, step)| (secs, Point2::from(series[step])))
+        .collect()
+}
+
+fn segment_intersection(
+    p0: Point2<f64>,
+    p1: Point2<f64>,
+    q0: Point2<f64>,
+    q1: Point2<f64>,
+) -> Option<(f64, f64)> {
+    let r = p1 - p0;
+    let s = q1 - q0;
+    let d = q0 - p0;
+
+    let denom = r.perp(&s);
+    if denom.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let u = d.perp(&s) / denom;
+    let w = d.perp(&r) / denom;
+    if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&w) {
+        Some((u, w))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute;
+
+    // straight path in equal steps
+    fn path(from: [f64; 2], to: [f64; 2], steps: usize) -> Vec<[f64; 2]> {
+        let u = |k: usize| k as f64 / steps as f64;
+        (0..=steps)
+            .map(|k| {
+                [
+                    from[0] + (to[0] - from[0]) * u(k),
+                    from[1] + (to[1] - from[1]) * u(k),
+                ]
+            })
+            .collect()
+    }
+
+    // a turning vehicle (id 0) and a pedestrian (id 1) of the default flows moved onto paths
+    fn spawn(veh_path: Vec<[f64; 2]>, ped_path: Vec<[f64; 2]>) -> forward::Forward {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+        forward.spawn_sampled(forward::AgentKind::LtVeh, 0, Some(veh_path));
+        forward.spawn_sampled(forward::AgentKind::Ped, 0, Some(ped_path));
+        forward
+    }
+
+    // one recorded step per second from the start time
+    fn record(agent: &mut forward::Agent, start_secs: f64) {
+        let len = agent.output.trajectory_series().len();
+        agent.history = (0..len).map(|k| (start_secs + k as f64, k)).collect();
+    }
+
+    #[test]
+    fn pet_at_crossing_point() {
+        let veh_path = path([-10.0, 0.0], [10.0, 0.0], 10);
+        let ped_path = path([0.0, -5.0], [0.0, 5.0], 10);
+        let mut forward = spawn(veh_path, ped_path);
+        record(&mut forward.agents[0], 0.0);
+        record(&mut forward.agents[1], 3.0);
+
+        let event = compute_pet(&forward.agents[1], &forward.agents[0]).unwrap();
+        assert_eq!((event.veh_id, event.ped_id), (0, 1));
+        assert!(event.point[0].abs() < 1e-9 && event.point[1].abs() < 1e-9);
+        assert!((event.veh_secs - 5.0).abs() < 1e-9);
+        assert!((event.ped_secs - 8.0).abs() < 1e-9);
+        assert!((event.pet - 3.0).abs() < 1e-9);
+        assert!((event.veh_speed - 2.0).abs() < 1e-9);
+        assert!((event.ped_speed - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pet_needs_crossing_paths() {
+        let veh_path = path([-10.0, 0.0], [10.0, 0.0], 10);
+        let ped_path = path([-5.0, 3.0], [5.0, 3.0], 10);
+        let mut forward = spawn(veh_path, ped_path);
+        record(&mut forward.agents[0], 0.0);
+        record(&mut forward.agents[1], 0.0);
+
+        let [veh, ped] = [&forward.agents[0], &forward.agents[1]];
+        assert_eq!(compute_pet(veh, ped), None);
+        // only vehicle and pedestrian pairs
+        assert_eq!(compute_pet(veh, veh), None);
+    }
+
+    // 10 m/s towards a pedestrian standing ahead
+    fn approaching(ped_at: [f64; 2]) -> forward::Forward {
+        let veh_path = path([-10.0, 0.0], [10.0, 0.0], 200);
+        let mut forward = spawn(veh_path, vec![ped_at; 2]);
+        if let Some(motion) = &mut forward.agents[0].motion {
+            motion.speed = 0.1 / compute::STEP;
+        }
+        forward
+    }
+
+    #[test]
+    fn ttc_closing_on_standing_pedestrian() {
+        // 20 m ahead, contact at 2 m
+        let forward = approaching([10.0, 0.0]);
+        let [veh, ped] = [&forward.agents[0], &forward.agents[1]];
+
+        let ttc = compute_ttc(veh, ped).unwrap();
+        assert!((ttc - 1.8).abs() < 1e-9);
+        assert_eq!(compute_ttc(ped, veh), Some(ttc));
+    }
+
+    #[test]
+    fn ttc_without_approach() {
+        for ped_at in [[-20.0, 0.0], [10.0, 5.0]] {
+            let forward = approaching(ped_at);
+            assert_eq!(compute_ttc(&forward.agents[0], &forward.agents[1]), None);
+        }
+        let forward = approaching([-9.0, 0.0]);
+        assert_eq!(
+            compute_ttc(&forward.agents[0], &forward.agents[1]),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn ttc_records_keep_minimum() {
+        let mut forward = approaching([10.0, 0.0]);
+        let mut records = Default::default();
+
+        update_ttc_records(&mut records, &forward.agents, 0.0);
+        forward.agents[0].step = 100;
+        update_ttc_records(&mut records, &forward.agents, 0.5);
+        forward.agents[0].step = 50;
+        forward.agents.reverse();
+        update_ttc_records(&mut records, &forward.agents, 1.0);
+
+        let record = &records[&[0, 1]];
+        assert!((record.min_ttc - 0.8).abs() < 1e-9);
+        assert_eq!(record.secs, 0.5);
+        assert_eq!(record.points, [[0.0, 0.0], [10.0, 0.0]]);
+    }
+}
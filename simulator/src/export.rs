@@ -0,0 +1,389 @@
+use std::io::Write;
+
+use crate::{compute, conflict, forward, interaction};
+
+const TRAJECTORY_HEADER: &str =
+    "id,kind,flow_index,spawn_secs,time,step,x,y,speed,heading,velocity,position,curvature";
+const AGENT_HEADER: &str = "id,kind,flow_index,spawn_secs,max_step,c_in,c_out,v_min,x_min,t_min,t_exit,t_o,x_o,v_1,v_2,x_1,x_2,x_3";
+
+// streams exited agents, fcd timesteps are written once no remaining agent can add to them
+pub struct Exporter {
+    trajectories: std::io::BufWriter<std::fs::File>,
+    agents: std::io::BufWriter<std::fs::File>,
+    fcd: Option<std::io::BufWriter<std::fs::File>>,
+    timesteps: std::collections::BTreeMap<i64, Vec<String>>,
+}
+
+impl Exporter {
+    pub fn create(output_dir: &std::path::Path, fcd: bool) -> std::io::Result<Self> {
+        let create = |name: &str| -> std::io::Result<_> {
+            let file = std::fs::File::create(output_dir.join(name))?;
+            Ok(std::io::BufWriter::new(file))
+        };
+        let mut exporter = Self {
+            trajectories: create("trajectories.csv")?,
+            agents: create("agents.csv")?,
+            fcd: None,
+            timesteps: Default::default(),
+        };
+
+        writeln!(exporter.trajectories, "{TRAJECTORY_HEADER}")?;
+        writeln!(exporter.agents, "{AGENT_HEADER}")?;
+        if fcd {
+            let mut writer = create("fcd.xml")?;
+            writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(
+                writer,
+                r#"<fcd-export xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:noNamespaceSchemaLocation="http://sumo.dlr.de/xsd/fcd_file.xsd">"#
+            )?;
+            exporter.fcd = Some(writer);
+        }
+        Ok(exporter)
+    }
+
+    pub fn write(&mut self, agents: &[forward::Agent]) -> std::io::Result<()> {
+        for agent in agents {
+            write_trajectory(&mut self.trajectories, agent)?;
+            write_agent(&mut self.agents, agent)?;
+            if self.fcd.is_none() {
+                continue;
+            }
+            for (k, &(time, _)) in agent.history.iter().enumerate() {
+                let key = (time / compute::STEP).round() as i64;
+                self.timesteps
+                    .entry(key)
+                    .or_default()
+                    .push(fcd_line(agent, k));
+            }
+        }
+        Ok(())
+    }
+
+    // timesteps before the given time are complete
+    pub fn flush_fcd(&mut self, secs: f64) -> std::io::Result<()> {
+        let Some(fcd) = &mut self.fcd else {
+            return Ok(());
+        };
+        let key = (secs / compute::STEP).round() as i64;
+        let rest = self.timesteps.split_off(&key);
+        for (key, lines) in std::mem::replace(&mut self.timesteps, rest) {
+            writeln!(
+                fcd,
+                r#"    <timestep time="{:.2}">"#,
+                key as f64 * compute::STEP
+            )?;
+            for line in lines {
+                writeln!(fcd, "{line}")?;
+            }
+            writeln!(fcd, "    </timestep>")?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.flush_fcd(f64::INFINITY)?;
+        if let Some(fcd) = &mut self.fcd {
+            writeln!(fcd, "</fcd-export>")?;
+            fcd.flush()?;
+        }
+        self.trajectories.flush()?;
+        self.agents.flush()
+    }
+}
+
+fn write_trajectory(writer: &mut impl Write, agent: &forward::Agent) -> std::io::Result<()> {
+    let series = agent.output.trajectory_series();
+
+    for (k, &(time, step)) in agent.history.iter().enumerate() {
+        let [x, y] = series[step];
+        let speed = history_speed(agent, k);
+        let heading = agent.heading(step).to_degrees();
+
+        let (velocity, position, curvature) = match &agent.output {
+            forward::AgentOutput::Veh(output) => (
+                output.velocity_series[step][1].to_string(),
+                output.position_series[step][1].to_string(),
+                output.curvature_series[step][1].to_string(),
+            ),
+            forward::AgentOutput::Ped(_) => Default::default(),
+        };
+
+        writeln!(
+            writer,
+            "{},{},{},{},{time},{step},{x},{y},{speed},{heading},{velocity},{position},{curvature}",
+            agent.id,
+            agent.kind.as_str(),
+            agent.flow_index,
+            agent.spawn_secs,
+        )?;
+    }
+    Ok(())
+}
+
+fn fcd_line(agent: &forward::Agent, k: usize) -> String {
+    let (_, step) = agent.history[k];
+    let [x, y] = agent.output.trajectory_series()[step];
+
+    // shift the ROAD_LENGTH extent into the positive quadrant like a SUMO network,
+    // angles are clockwise from north
+    let x = x + compute::ROAD_LENGTH;
+    let y = y + compute::ROAD_LENGTH;
+    let angle = (90.0 - agent.heading(step).to_degrees()).rem_euclid(360.0);
+    let speed = history_speed(agent, k);
+
+    let tag = match agent.output {
+        forward::AgentOutput::Veh(_) => "vehicle",
+        forward::AgentOutput::Ped(_) => "person",
+    };
+    format!(
+        r#"        <{tag} id="{}" x="{x:.2}" y="{y:.2}" angle="{angle:.2}" type="{}" speed="{speed:.2}"/>"#,
+        agent.id,
+        agent.kind.as_str(),
+    )
+}
+
+fn write_agent(writer: &mut impl Write, agent: &forward::Agent) -> std::io::Result<()> {
+    let (max_step, veh_columns, ped_columns) = match &agent.output {
+        forward::AgentOutput::Veh(v) => (
+            v.max_step,
+            format!(
+                "{},{},{},{},{},{},{},{}",
+                v.c_in, v.c_out, v.v_min, v.x_min, v.t_min, v.t_exit, v.t_o, v.x_o
+            ),
+            ",,,,".to_string(),
+        ),
+        forward::AgentOutput::Ped(v) => (
+            v.max_step,
+            ",,,,,,,".to_string(),
+            format!("{},{},{},{},{}", v.v_1, v.v_2, v.x_1, v.x_2, v.x_3),
+        ),
+    };
+
+    writeln!(
+        writer,
+        "{},{},{},{},{max_step},{veh_columns},{ped_columns}",
+        agent.id,
+        agent.kind.as_str(),
+        agent.flow_index,
+        agent.spawn_secs,
+    )
+}
+
+pub fn write_pet_events(
+    events: &[conflict::PetEvent],
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    writeln!(
+        writer,
+        "veh_id,veh_kind,veh_flow_index,ped_id,ped_kind,ped_flow_index,x,y,veh_secs,ped_secs,pet,veh_speed,ped_speed"
+    )?;
+    for event in events {
+        let [x, y] = event.point;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{x},{y},{},{},{},{},{}",
+            event.veh_id,
+            event.veh_kind.as_str(),
+            event.veh_flow_index,
+            event.ped_id,
+            event.ped_kind.as_str(),
+            event.ped_flow_index,
+            event.veh_secs,
+            event.ped_secs,
+            event.pet,
+            event.veh_speed,
+            event.ped_speed,
+        )?;
+    }
+
+    writer.flush()
+}
+
+pub fn write_yield_events(
+    events: &[interaction::YieldEvent],
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    writeln!(
+        writer,
+        "veh_id,veh_kind,veh_flow_index,ped_id,ped_kind,ped_flow_index,secs,x,y,distance,speed,yielded"
+    )?;
+    for event in events {
+        let [x, y] = event.point;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{x},{y},{},{},{}",
+            event.veh_id,
+            event.veh_kind.as_str(),
+            event.veh_flow_index,
+            event.ped_id,
+            event.ped_kind.as_str(),
+            event.ped_flow_index,
+            event.secs,
+            event.distance,
+            event.speed,
+            event.yielded,
+        )?;
+    }
+
+    writer.flush()
+}
+
+pub fn write_red_light_events(
+    events: &[interaction::RedLightEvent],
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    writeln!(
+        writer,
+        "veh_id,veh_kind,veh_flow_index,secs,red_secs,x,y,speed"
+    )?;
+    for event in events {
+        let [x, y] = event.point;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{x},{y},{}",
+            event.veh_id,
+            event.veh_kind.as_str(),
+            event.veh_flow_index,
+            event.secs,
+            event.red_secs,
+            event.speed,
+        )?;
+    }
+
+    writer.flush()
+}
+
+pub fn write_ttc_records(
+    records: &ahash::AHashMap<[usize; 2], conflict::TtcRecord>,
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    let mut records: Vec<_> = records.values().collect();
+    records.sort_by_key(|v| v.ids);
+
+    writeln!(
+        writer,
+        "id_0,kind_0,flow_index_0,id_1,kind_1,flow_index_1,min_ttc,secs,x_0,y_0,x_1,y_1"
+    )?;
+    for record in records {
+        let [[x0, y0], [x1, y1]] = record.points;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{x0},{y0},{x1},{y1}",
+            record.ids[0],
+            record.kinds[0].as_str(),
+            record.flow_indices[0],
+            record.ids[1],
+            record.kinds[1].as_str(),
+            record.flow_indices[1],
+            record.min_ttc,
+            record.secs,
+        )?;
+    }
+
+    writer.flush()
+}
+
+// actual speed between history records, the sampled one before the first move
+fn history_speed(agent: &forward::Agent, k: usize) -> f64 {
+    let series = agent.output.trajectory_series();
+    let (time, step) = agent.history[k];
+
+    match k.checked_sub(1).map(|v| agent.history[v]) {
+        Some((prev_time, prev_step)) if time > prev_time => {
+            let [x0, y0] = series[prev_step];
+            let [x1, y1] = series[step];
+            f64::hypot(x1 - x0, y1 - y0) / (time - prev_time)
+        }
+        _ => {
+            let [vx, vy] = agent.velocity_at(step);
+            f64::hypot(vx, vy)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a pedestrian walking north at 1 m/s, recorded at its first two steps
+    fn walking() -> forward::Forward {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+        let path = vec![[0.0, 0.0], [0.0, 0.01], [0.0, 0.02]];
+        let agent = forward.spawn_sampled(forward::AgentKind::Ped, 0, Some(path));
+        agent.history = vec![(0.0, 0), (0.01, 1)];
+        if let forward::AgentOutput::Ped(output) = &mut agent.output {
+            *output = compute::PedOutput {
+                v_1: 1.5,
+                v_2: 1.25,
+                x_1: 2.0,
+                x_2: 4.0,
+                x_3: 6.0,
+                max_step: 2,
+                ..output.clone()
+            };
+        }
+        forward
+    }
+
+    #[test]
+    fn trajectory_rows() {
+        let forward = walking();
+        let mut buf = vec![];
+        writeln!(buf, "{TRAJECTORY_HEADER}").unwrap();
+        write_trajectory(&mut buf, &forward.agents[0]).unwrap();
+
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], TRAJECTORY_HEADER);
+        assert_eq!(lines[1], "0,ped,0,0,0,0,0,0,1,90,,,");
+        assert_eq!(
+            lines[2].split(',').count(),
+            TRAJECTORY_HEADER.split(',').count()
+        );
+    }
+
+    #[test]
+    fn agent_row() {
+        let forward = walking();
+        let mut buf = vec![];
+        writeln!(buf, "{AGENT_HEADER}").unwrap();
+        write_agent(&mut buf, &forward.agents[0]).unwrap();
+
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines, [AGENT_HEADER, "0,ped,0,0,2,,,,,,,,,1.5,1.25,2,4,6"]);
+    }
+
+    #[test]
+    fn fcd_vehicle_east_and_person_north() {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+        let path = vec![[0.0, 0.0], [0.1, 0.0], [0.2, 0.0]];
+        let veh = forward.spawn_sampled(forward::AgentKind::LtVeh, 0, Some(path));
+        veh.history = vec![(0.0, 0), (0.01, 1)];
+        let path = vec![[0.0, 0.0], [0.0, 0.01], [0.0, 0.02]];
+        let ped = forward.spawn_sampled(forward::AgentKind::Ped, 0, Some(path));
+        ped.history = vec![(0.0, 0), (0.01, 1)];
+
+        assert_eq!(
+            fcd_line(&forward.agents[0], 1),
+            r#"        <vehicle id="0" x="64.10" y="64.00" angle="90.00" type="lt_veh" speed="10.00"/>"#
+        );
+        assert_eq!(
+            fcd_line(&forward.agents[1], 1),
+            r#"        <person id="1" x="64.00" y="64.01" angle="0.00" type="ped" speed="1.00"/>"#
+        );
+    }
+}
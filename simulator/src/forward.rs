@@ -1,8 +1,15 @@
-use crate::{compute, settings};
+use crate::{actuated, compute, conflict, interaction, model, settings, validator};
+
+// observed flows are counts over this window divided by it, so rates are per second,
+// the default crossing coefficients only give valid weibull parameters at these rates
+pub const FLOW_WINDOW: f64 = 60.0;
+// vehicles waiting per lane before further arrivals spill back out of the modelled approach
+pub const MAX_PENDING: usize = 50;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum VehSignalState {
     Green,
+    GreenArrow,
     Yellow,
     Red,
 }
@@ -14,82 +21,368 @@ pub enum PedSignalState {
     Red,
 }
 
+impl settings::VehSignal {
+    // protected arrow within the green
+    pub fn arrow_window(&self, green_secs: f64) -> Option<[f64; 2]> {
+        let protected_secs = f64::min(self.protected_secs, green_secs);
+        match self.turn_mode {
+            settings::TurnMode::Permissive => None,
+            settings::TurnMode::Protected => Some([0.0, green_secs]),
+            settings::TurnMode::LeadingProtectedPermissive => Some([0.0, protected_secs]),
+            settings::TurnMode::LaggingProtectedPermissive => {
+                Some([green_secs - protected_secs, green_secs])
+            }
+        }
+    }
+
+    // the cycle is the timebase, a red that does not fill it is reported by the validator
+    pub fn state_at(&self, secs: f64) -> (VehSignalState, f64) {
+        let intime = (secs - self.offset_secs).rem_euclid(self.cycle_secs);
+        let arrow = self
+            .arrow_window(self.green_secs)
+            .is_some_and(|[s0, s1]| s0 <= intime && intime < s1);
+        if intime < self.green_secs && arrow {
+            (VehSignalState::GreenArrow, intime)
+        } else if intime < self.green_secs {
+            (VehSignalState::Green, intime)
+        } else if intime < self.green_secs + self.yellow_secs {
+            (VehSignalState::Yellow, intime - self.green_secs)
+        } else {
+            (
+                VehSignalState::Red,
+                intime - self.green_secs - self.yellow_secs,
+            )
+        }
+    }
+}
+
+impl settings::PedSignal {
+    pub fn state_at(&self, secs: f64) -> (PedSignalState, f64) {
+        let intime = (secs - self.offset_secs).rem_euclid(self.cycle_secs);
+        if intime < self.green_secs {
+            (PedSignalState::Green, intime)
+        } else if intime < self.green_secs + self.blink_secs {
+            (PedSignalState::Blink, intime - self.green_secs)
+        } else {
+            (
+                PedSignalState::Red,
+                intime - self.green_secs - self.blink_secs,
+            )
+        }
+    }
+}
+
+impl settings::Settings {
+    pub fn veh_flow(&self, kind: AgentKind, flow_index: usize) -> Option<&settings::VehFlow> {
+        match kind {
+            AgentKind::StVeh => self.st_veh_flows.get(flow_index),
+            AgentKind::LtVeh => self.lt_veh_flows.get(flow_index),
+            AgentKind::RtVeh => self.rt_veh_flows.get(flow_index),
+            AgentKind::Ped | AgentKind::IgPed => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AgentKind {
+    StVeh,
+    LtVeh,
+    RtVeh,
+    Ped,
+    IgPed,
+}
+
+impl AgentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentKind::StVeh => "st_veh",
+            AgentKind::LtVeh => "lt_veh",
+            AgentKind::RtVeh => "rt_veh",
+            AgentKind::Ped => "ped",
+            AgentKind::IgPed => "ig_ped",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AgentOutput {
+    Veh(compute::VehOutput),
+    Ped(compute::PedOutput),
+}
+
+impl AgentOutput {
+    pub fn trajectory_series(&self) -> &[[f64; 2]] {
+        match self {
+            AgentOutput::Veh(output) => &output.trajectory_series,
+            AgentOutput::Ped(output) => &output.trajectory_series,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Agent {
+    pub id: usize,
+    pub kind: AgentKind,
+    pub flow_index: usize,
+    pub spawn_secs: f64,
+    pub step: usize,
+    pub history: Vec<(f64, usize)>,
+    pub output: AgentOutput,
+    pub motion: Option<interaction::VehMotion>,
+}
+
+impl Agent {
+    pub fn position(&self) -> [f64; 2] {
+        self.output.trajectory_series()[self.step]
+    }
+
+    pub fn heading(&self, step: usize) -> f64 {
+        let series = self.output.trajectory_series();
+        if series.len() < 2 {
+            return 0.0;
+        }
+        let i1 = usize::clamp(step + 1, 1, series.len() - 1);
+        let [x0, y0] = series[i1 - 1];
+        let [x1, y1] = series[i1];
+        f64::atan2(y1 - y0, x1 - x0)
+    }
+
+    pub fn velocity(&self) -> [f64; 2] {
+        match &self.motion {
+            Some(motion) => {
+                let heading = self.heading(self.step);
+                [motion.speed * heading.cos(), motion.speed * heading.sin()]
+            }
+            None => self.velocity_at(self.step),
+        }
+    }
+
+    pub fn velocity_at(&self, step: usize) -> [f64; 2] {
+        let series = self.output.trajectory_series();
+        if series.len() < 2 {
+            return [0.0; 2];
+        }
+        let i1 = usize::clamp(step + 1, 1, series.len() - 1);
+        let [x0, y0] = series[i1 - 1];
+        let [x1, y1] = series[i1];
+        [(x1 - x0) / compute::STEP, (y1 - y0) / compute::STEP]
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PendingVeh {
+    pub kind: AgentKind,
+    pub flow_index: usize,
+    pub arrival_secs: f64,
+    pub lane: (settings::Dir, usize),
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Forward {
     pub settings: settings::Settings,
     pub veh_signals: ahash::AHashMap<[settings::Dir; 2], VehSignalState>,
+    pub veh_signal_secs: ahash::AHashMap<[settings::Dir; 2], f64>,
     pub ped_signals: ahash::AHashMap<[settings::Dir; 2], PedSignalState>,
+    pub ped_signal_secs: ahash::AHashMap<[settings::Dir; 2], f64>,
     pub elapsed_time: f64,
     pub next_spawns: ahash::AHashMap<String, f64>,
-    pub trajectories: Vec<(Vec<[f64; 2]>, usize)>,
+    pub agents: Vec<Agent>,
+    pub pending: Vec<PendingVeh>,
+    pub spillback: ahash::AHashMap<(settings::Dir, usize), usize>,
+    pub archive: Vec<Agent>,
+    pub next_id: usize,
+    pub pet_events: Vec<conflict::PetEvent>,
+    pub ttc_records: ahash::AHashMap<[usize; 2], conflict::TtcRecord>,
+    pub yield_events: Vec<interaction::YieldEvent>,
+    pub red_light_events: Vec<interaction::RedLightEvent>,
+    pub flow_log: std::collections::VecDeque<(f64, AgentKind, [settings::Dir; 2])>,
+    pub controller: Option<actuated::Controller>,
+    pub model: model::BehaviourModel,
+    pub rng: rand::rngs::StdRng,
 }
 
 impl Forward {
-    pub fn new(settings: settings::Settings) -> Self {
-        Self {
+    pub fn new(mut settings: settings::Settings) -> std::io::Result<Self> {
+        settings.apply_signal_plan();
+        // behaviour model, recorded into the scenario
+        let model = model::BehaviourModel::from_settings(&settings).map_err(|err| {
+            let msg = format!("failed to load model {}: {err}", settings.model_path);
+            std::io::Error::new(err.kind(), msg)
+        })?;
+        if !settings.model_version.is_empty() && settings.model_version != model.label() {
+            log::warn!(
+                "scenario recorded model {} but {} is loaded",
+                settings.model_version,
+                model.label()
+            );
+        }
+        settings.model_version = model.label();
+        let rng = rand::SeedableRng::seed_from_u64(settings.seed);
+        let controller = match settings.control {
+            settings::ControlMode::Fixed => None,
+            settings::ControlMode::Actuated => Some(actuated::Controller::new(&settings)),
+        };
+        Ok(Self {
             settings,
             veh_signals: ahash::AHashMap::new(),
+            veh_signal_secs: ahash::AHashMap::new(),
             ped_signals: ahash::AHashMap::new(),
+            ped_signal_secs: ahash::AHashMap::new(),
             elapsed_time: 0.0,
             next_spawns: Default::default(),
-            trajectories: Default::default(),
-        }
+            agents: Default::default(),
+            pending: Default::default(),
+            spillback: Default::default(),
+            archive: Default::default(),
+            next_id: 0,
+            pet_events: Default::default(),
+            ttc_records: Default::default(),
+            yield_events: Default::default(),
+            red_light_events: Default::default(),
+            flow_log: Default::default(),
+            controller,
+            model,
+            rng,
+        })
     }
 
     pub fn forward(&mut self, delta_secs: f64) {
-        let rng = &mut rand::thread_rng();
+        match &mut self.controller {
+            None => {
+                // vehicle signals
+                for signal in &self.settings.veh_signals {
+                    let (state, secs) = signal.state_at(self.elapsed_time);
+                    self.veh_signals
+                        .insert([signal.src_dir, signal.dst_dir], state);
+                    self.veh_signal_secs
+                        .insert([signal.src_dir, signal.dst_dir], secs);
+                }
 
-        // vehicle signals
-        for i in 0..self.settings.veh_signals.len() {
-            let signal = &self.settings.veh_signals[i];
+                // pedestrian signals
+                for signal in &self.settings.ped_signals {
+                    let (state, secs) = signal.state_at(self.elapsed_time);
+                    self.ped_signals
+                        .insert([signal.src_dir, signal.dst_dir], state);
+                    self.ped_signal_secs
+                        .insert([signal.src_dir, signal.dst_dir], secs);
+                }
+            }
+            Some(controller) => {
+                // actuated signals from detectors and push-buttons
+                let occupied = controller.detector_occupancy(&self.settings, &self.agents);
+                controller.update(&self.settings.actuated, &occupied, delta_secs);
 
-            let s0 = signal.offset_secs;
-            let s1 = signal.offset_secs + signal.green_secs;
-            let s2 = signal.offset_secs + signal.green_secs + signal.yellow_secs;
-
-            let intime = self.elapsed_time % signal.cycle_secs;
-            if s0 <= intime && intime < s1 {
-                self.veh_signals
-                    .insert([signal.src_dir, signal.dst_dir], VehSignalState::Green);
-            } else if s1 <= intime && intime < s2 {
-                self.veh_signals
-                    .insert([signal.src_dir, signal.dst_dir], VehSignalState::Yellow);
-            } else {
-                self.veh_signals
-                    .insert([signal.src_dir, signal.dst_dir], VehSignalState::Red);
+                // the controller runs a lagging arrow after the stage clears,
+                // a signal left out of every stage stays red
+                let mut arrows = vec![];
+                for signal in &self.settings.veh_signals {
+                    let key = [signal.src_dir, signal.dst_dir];
+                    let (state, secs) = controller
+                        .veh_state(&self.settings.actuated, key)
+                        .unwrap_or((VehSignalState::Red, self.elapsed_time));
+                    let arrow = match signal.turn_mode {
+                        settings::TurnMode::Protected => true,
+                        settings::TurnMode::LeadingProtectedPermissive => {
+                            secs < signal.protected_secs
+                        }
+                        _ => false,
+                    };
+                    let state = match state {
+                        VehSignalState::Green if arrow => VehSignalState::GreenArrow,
+                        _ => state,
+                    };
+                    // a protected turn keeps its conflicts red through its yellow
+                    let protected = signal.turn_mode == settings::TurnMode::Protected;
+                    if state == VehSignalState::GreenArrow
+                        || protected && state == VehSignalState::Yellow
+                    {
+                        arrows.push(validator::Movement::Veh(key));
+                    }
+                    self.veh_signals.insert(key, state);
+                    self.veh_signal_secs.insert(key, secs);
+                }
+
+                for signal in &self.settings.ped_signals {
+                    let key = [signal.src_dir, signal.dst_dir];
+                    let (state, secs) = controller
+                        .ped_state(&self.settings.actuated, key)
+                        .unwrap_or((PedSignalState::Red, self.elapsed_time));
+                    self.ped_signals.insert(key, state);
+                    self.ped_signal_secs.insert(key, secs);
+                }
+
+                // protected arrows hold the opposing and crosswalk movements they turn across red
+                for arrow in arrows {
+                    for (&key, state) in &mut self.veh_signals {
+                        let kind = validator::conflict(
+                            &self.settings,
+                            arrow,
+                            validator::Movement::Veh(key),
+                        );
+                        if kind == Some(validator::ConflictKind::Permissive) {
+                            *state = VehSignalState::Red;
+                        }
+                    }
+                    for (&key, state) in &mut self.ped_signals {
+                        let kind = validator::conflict(
+                            &self.settings,
+                            arrow,
+                            validator::Movement::Ped(key),
+                        );
+                        if kind == Some(validator::ConflictKind::Permissive) {
+                            *state = PedSignalState::Red;
+                        }
+                    }
+                }
             }
         }
 
-        // pedestrian signals
-        for i in 0..self.settings.ped_signals.len() {
-            let signal = &self.settings.ped_signals[i];
+        // vehicles waiting to enter, only the head of each lane may go
+        let mut lanes = ahash::AHashSet::new();
+        for pending in std::mem::take(&mut self.pending) {
+            let flow = self
+                .settings
+                .veh_flow(pending.kind, pending.flow_index)
+                .unwrap();
+            let start = interaction::entry(&self.settings, flow).map(|(_, start)| start);
+            if !lanes.insert(pending.lane)
+                || start.is_some_and(|v| interaction::entry_blocked(&self.agents, pending.lane, v))
+            {
+                self.pending.push(pending);
+                continue;
+            }
+            self.enter(pending.kind, pending.flow_index);
+        }
 
-            let s0 = signal.offset_secs;
-            let s1 = signal.offset_secs + signal.green_secs;
-            let s2 = signal.offset_secs + signal.green_secs + signal.blink_secs;
-
-            let intime = self.elapsed_time % signal.cycle_secs;
-            if s0 <= intime && intime < s1 {
-                self.ped_signals
-                    .insert([signal.src_dir, signal.dst_dir], PedSignalState::Green);
-            } else if s1 <= intime && intime < s2 {
-                self.ped_signals
-                    .insert([signal.src_dir, signal.dst_dir], PedSignalState::Blink);
-            } else {
-                self.ped_signals
-                    .insert([signal.src_dir, signal.dst_dir], PedSignalState::Red);
+        // observed flows
+        while let Some((secs, _, _)) = self.flow_log.front() {
+            if self.elapsed_time - secs <= FLOW_WINDOW {
+                break;
             }
+            self.flow_log.pop_front();
         }
 
-        // left-turn vehicle
-        for i in 0..self.settings.lt_veh_flows.len() {
-            let flow = &self.settings.lt_veh_flows[i];
-            let signal = self.veh_signals.get(&[flow.src_dir, flow.dst_dir]).unwrap();
+        // straight vehicle
+        for i in 0..self.settings.st_veh_flows.len() {
+            let flow = &self.settings.st_veh_flows[i];
 
-            if signal == &VehSignalState::Red {
+            let id = format!("st_veh_{i}");
+            let next_spawn = self.next_spawns.entry(id).or_insert(0.0);
+            *next_spawn -= delta_secs;
+            if *next_spawn > 0.0 {
                 continue;
             }
 
+            let distr = rand_distr::Exp::new(flow.density * flow.v_in_mean).unwrap();
+            *next_spawn = rand::Rng::sample(&mut self.rng, distr);
+
+            self.arrive(AgentKind::StVeh, i);
+        }
+
+        // left-turn vehicle
+        for i in 0..self.settings.lt_veh_flows.len() {
+            let flow = &self.settings.lt_veh_flows[i];
+
             let id = format!("lt_veh_{i}");
             let next_spawn = self.next_spawns.entry(id).or_insert(0.0);
             *next_spawn -= delta_secs;
@@ -97,21 +390,15 @@ impl Forward {
                 continue;
             }
 
-            if let Some(output) = compute::compute_lt_veh(&self.settings, flow) {
-                self.trajectories.push((output.trajectory_series, 0));
-            }
             let distr = rand_distr::Exp::new(flow.density * flow.v_in_mean).unwrap();
-            *next_spawn = rand::Rng::sample(rng, distr);
+            *next_spawn = rand::Rng::sample(&mut self.rng, distr);
+
+            self.arrive(AgentKind::LtVeh, i);
         }
 
         // right-turn vehicle
         for i in 0..self.settings.rt_veh_flows.len() {
             let flow = &self.settings.rt_veh_flows[i];
-            let signal = self.veh_signals.get(&[flow.src_dir, flow.dst_dir]).unwrap();
-
-            if signal == &VehSignalState::Red {
-                continue;
-            }
 
             let id = format!("rt_veh_{i}");
             let next_spawn = self.next_spawns.entry(id).or_insert(0.0);
@@ -120,23 +407,43 @@ impl Forward {
                 continue;
             }
 
-            if let Some(output) = compute::compute_rt_veh(&self.settings, flow) {
-                self.trajectories.push((output.trajectory_series, 0));
-            }
             let distr = rand_distr::Exp::new(flow.density * flow.v_in_mean).unwrap();
-            *next_spawn = rand::Rng::sample(rng, distr);
+            *next_spawn = rand::Rng::sample(&mut self.rng, distr);
+
+            self.arrive(AgentKind::RtVeh, i);
         }
 
         // pedestrian
         for i in 0..self.settings.ped_flows.len() {
             let flow = &self.settings.ped_flows[i];
-            let signal0 = self.ped_signals.get(&[flow.src, flow.dst]);
-            let signal1 = self.ped_signals.get(&[flow.dst, flow.src]);
-            let signal = Option::or(signal0, signal1).unwrap();
+            let key = if self.ped_signals.contains_key(&[flow.src, flow.dst]) {
+                [flow.src, flow.dst]
+            } else {
+                [flow.dst, flow.src]
+            };
+            let signal = self.ped_signals.get(&key).unwrap();
+            let secs = self.ped_signal_secs.get(&key).unwrap();
 
             if signal != &PedSignalState::Green {
+                // push-button, the pedestrian waits for the walk
+                if let Some(controller) = &mut self.controller {
+                    let next_spawn = self.next_spawns.entry(format!("ped_{i}")).or_insert(0.0);
+                    *next_spawn -= delta_secs;
+                    if *next_spawn <= 0.0 {
+                        controller.call_ped(key);
+                    }
+                }
                 continue;
             }
+            let (lt_veh_flow, forward_ped_flow, backward_ped_flow) = self.observed_flows(flow);
+            let context = compute::PedContext {
+                a_green: *secs,
+                t_blink: 0.0,
+                t_remaining: None,
+                lt_veh_flow,
+                forward_ped_flow,
+                backward_ped_flow,
+            };
 
             let id = format!("ped_{i}");
             let next_spawn = self.next_spawns.entry(id).or_insert(0.0);
@@ -145,23 +452,55 @@ impl Forward {
                 continue;
             }
 
-            if let Some(output) = compute::compute_ped(&self.settings, flow) {
-                self.trajectories.push((output.trajectory_series, 0));
-            }
+            let output = compute::compute_ped(
+                &self.settings,
+                &self.model.ped,
+                flow,
+                &context,
+                &mut self.rng,
+            );
             let distr = rand_distr::Exp::new(flow.density * flow.v_in_mean).unwrap();
-            *next_spawn = rand::Rng::sample(rng, distr);
+            *next_spawn = rand::Rng::sample(&mut self.rng, distr);
+
+            if let Some(output) = output {
+                self.spawn(AgentKind::Ped, i, AgentOutput::Ped(output));
+            }
         }
 
         // inter-green pedestrian
         for i in 0..self.settings.ig_ped_flows.len() {
-            let flow = &self.settings.ped_flows[i];
-            let signal0 = self.ped_signals.get(&[flow.src, flow.dst]);
-            let signal1 = self.ped_signals.get(&[flow.dst, flow.src]);
-            let signal = Option::or(signal0, signal1).unwrap();
+            let flow = &self.settings.ig_ped_flows[i];
+            let key = if self.ped_signals.contains_key(&[flow.src, flow.dst]) {
+                [flow.src, flow.dst]
+            } else {
+                [flow.dst, flow.src]
+            };
+            let signal = self.ped_signals.get(&key).unwrap();
+            let secs = self.ped_signal_secs.get(&key).unwrap();
 
             if signal != &PedSignalState::Blink {
                 continue;
             }
+            let (lt_veh_flow, forward_ped_flow, backward_ped_flow) = self.observed_flows(flow);
+
+            // countdown shows the remaining blink
+            let t_remaining = self
+                .settings
+                .ped_signals
+                .iter()
+                .find(|v| [v.src_dir, v.dst_dir] == key && v.countdown)
+                .map(|v| match self.controller {
+                    Some(_) => self.settings.actuated.blink_secs - secs,
+                    None => v.blink_secs - secs,
+                });
+            let context = compute::PedContext {
+                a_green: 0.0,
+                t_blink: *secs,
+                t_remaining,
+                lt_veh_flow,
+                forward_ped_flow,
+                backward_ped_flow,
+            };
 
             let id = format!("ig_ped_{i}");
             let next_spawn = self.next_spawns.entry(id).or_insert(0.0);
@@ -170,41 +509,353 @@ impl Forward {
                 continue;
             }
 
-            if let Some(output) = compute::compute_ig_ped(&self.settings, flow) {
-                self.trajectories.push((output.trajectory_series, 0));
-            }
+            let output = compute::compute_ig_ped(
+                &self.settings,
+                &self.model.ig_ped,
+                flow,
+                &context,
+                &mut self.rng,
+            );
             let distr = rand_distr::Exp::new(flow.density * flow.v_in_mean).unwrap();
-            *next_spawn = rand::Rng::sample(rng, distr);
+            *next_spawn = rand::Rng::sample(&mut self.rng, distr);
+
+            if let Some(output) = output {
+                self.spawn(AgentKind::IgPed, i, AgentOutput::Ped(output));
+            }
+        }
+
+        let step0 = (self.elapsed_time / compute::STEP).round() as usize;
+        let step1 = ((self.elapsed_time + delta_secs) / compute::STEP).round() as usize;
+
+        self.elapsed_time += delta_secs;
+
+        // stop lines and protected turns
+        for agent in &mut self.agents {
+            let Some(motion) = &mut agent.motion else {
+                continue;
+            };
+            let flow = self
+                .settings
+                .veh_flow(agent.kind, agent.flow_index)
+                .unwrap();
+            let signal = self.veh_signals.get(&[flow.src_dir, flow.dst_dir]);
+            motion.stop = match signal {
+                Some(VehSignalState::Yellow) => {
+                    if motion.go.is_none() {
+                        motion.go = Some(interaction::decide_go(motion, &mut self.rng));
+                    }
+                    motion.go == Some(false)
+                }
+                Some(VehSignalState::Red) => {
+                    motion.go != Some(true)
+                        || interaction::can_stop(motion, interaction::COMFORT_DECEL)
+                }
+                _ => {
+                    motion.go = None;
+                    false
+                }
+            };
+
+            // a turn entering on the arrow keeps its priority through the intersection
+            let stop_distance = motion.approach.as_ref().and_then(|v| v.stop_distance);
+            if stop_distance.is_none_or(|v| motion.distance < v) {
+                motion.protected = signal == Some(&VehSignalState::GreenArrow);
+            }
         }
 
+        // gap acceptance and yielding
+        interaction::update_gap_acceptance(&mut self.agents);
+        interaction::update_crosswalks(&mut self.agents, self.elapsed_time, &mut self.yield_events);
+
+        // car following
+        interaction::update_leaders(&mut self.agents);
+
         let mut remove_stack = vec![];
-        for i in 0..self.trajectories.len() {
-            let (trajectory, step) = &mut self.trajectories[i];
+        for i in 0..self.agents.len() {
+            let agent = &mut self.agents[i];
+            let last_step = agent.output.trajectory_series().len() - 1;
 
-            *step += delta_secs.div_euclid(compute::STEP) as usize;
+            match (&mut agent.motion, &agent.output) {
+                (Some(motion), AgentOutput::Veh(output)) => {
+                    let distance = motion.distance;
+                    for _ in step0..step1 {
+                        let [_, v] = output.velocity_series[agent.step];
+                        motion.advance(v, compute::STEP);
+                        agent.step = motion.step_at(agent.step);
+                    }
 
-            if *step >= trajectory.len() {
-                remove_stack.push(i);
+                    // red-light running
+                    let stop_distance = motion.approach.as_ref().and_then(|v| v.stop_distance);
+                    let flow = self
+                        .settings
+                        .veh_flow(agent.kind, agent.flow_index)
+                        .unwrap();
+                    let key = [flow.src_dir, flow.dst_dir];
+                    let signal = self.veh_signals.get(&key);
+                    let crossed =
+                        stop_distance.is_some_and(|v| distance < v && v <= motion.distance);
+                    if crossed && signal == Some(&VehSignalState::Red) {
+                        self.red_light_events.push(interaction::RedLightEvent {
+                            veh_id: agent.id,
+                            veh_kind: agent.kind,
+                            veh_flow_index: agent.flow_index,
+                            secs: self.elapsed_time,
+                            red_secs: *self.veh_signal_secs.get(&key).unwrap(),
+                            point: agent.output.trajectory_series()[agent.step],
+                            speed: motion.speed,
+                        });
+                    }
+
+                    if motion.distance >= motion.last_distance() {
+                        remove_stack.push(i);
+                    }
+                }
+                _ => {
+                    agent.step += step1 - step0;
+                    if agent.step > last_step {
+                        agent.step = last_step;
+                        remove_stack.push(i);
+                    }
+                }
             }
+            agent.history.push((self.elapsed_time, agent.step));
         }
         while let Some(i) = remove_stack.pop() {
-            self.trajectories.swap_remove(i);
+            let agent = self.agents.swap_remove(i);
+
+            // post-encroachment against agents that exited while this one was active
+            for other in self.archive.iter().rev() {
+                if other.history.last().unwrap().0 < agent.spawn_secs {
+                    break;
+                }
+                if let Some(event) = conflict::compute_pet(&agent, other) {
+                    self.pet_events.push(event);
+                }
+            }
+
+            self.archive.push(agent);
         }
 
-        self.elapsed_time += delta_secs;
+        conflict::update_ttc_records(&mut self.ttc_records, &self.agents, self.elapsed_time);
+    }
+
+    // post-encroachment of agents still active at the end of a run, on their tracks so far
+    pub fn flush_pet_events(&mut self) {
+        for (i, agent) in self.agents.iter().enumerate() {
+            for other in self.archive.iter().rev() {
+                if other.history.last().unwrap().0 < agent.spawn_secs {
+                    break;
+                }
+                if let Some(event) = conflict::compute_pet(agent, other) {
+                    self.pet_events.push(event);
+                }
+            }
+            for other in &self.agents[i + 1..] {
+                if let Some(event) = conflict::compute_pet(agent, other) {
+                    self.pet_events.push(event);
+                }
+            }
+        }
+    }
+
+    // exited agents no active agent can still pair with for post-encroachment
+    pub fn drain_archive(&mut self) -> Vec<Agent> {
+        let spawn_secs = self
+            .agents
+            .iter()
+            .map(|v| v.spawn_secs)
+            .fold(f64::INFINITY, f64::min);
+        let n = self
+            .archive
+            .partition_point(|v| v.history.last().unwrap().0 < spawn_secs);
+        self.archive.drain(..n).collect()
+    }
+
+    // arrivals behind waiting vehicles queue without an output until they enter
+    fn arrive(&mut self, kind: AgentKind, flow_index: usize) {
+        let flow = self.settings.veh_flow(kind, flow_index).unwrap();
+        let Some((lane, start)) = interaction::entry(&self.settings, flow) else {
+            self.enter(kind, flow_index);
+            return;
+        };
+        let waiting = self.pending.iter().filter(|v| v.lane == lane).count();
+        if waiting == 0 && !interaction::entry_blocked(&self.agents, lane, start) {
+            self.enter(kind, flow_index);
+        } else if waiting < MAX_PENDING {
+            self.pending.push(PendingVeh {
+                kind,
+                flow_index,
+                arrival_secs: self.elapsed_time,
+                lane,
+            });
+        } else {
+            *self.spillback.entry(lane).or_default() += 1;
+        }
+    }
+
+    fn enter(&mut self, kind: AgentKind, flow_index: usize) {
+        let flow = self.settings.veh_flow(kind, flow_index).unwrap();
+        let output = match kind {
+            AgentKind::StVeh => compute::compute_st_veh(&self.settings, flow, &mut self.rng),
+            AgentKind::LtVeh => {
+                compute::compute_lt_veh(&self.settings, &self.model.lt_veh, flow, &mut self.rng)
+                    .map(compute::extend_upstream)
+            }
+            AgentKind::RtVeh => {
+                compute::compute_rt_veh(&self.settings, &self.model.rt_veh, flow, &mut self.rng)
+                    .map(compute::extend_upstream)
+            }
+            AgentKind::Ped | AgentKind::IgPed => None,
+        };
+        if let Some(output) = output {
+            self.spawn(kind, flow_index, AgentOutput::Veh(output));
+        }
+    }
+
+    fn spawn(&mut self, kind: AgentKind, flow_index: usize, output: AgentOutput) {
+        if output.trajectory_series().is_empty() {
+            return;
+        }
+
+        let motion = match &output {
+            AgentOutput::Veh(output) => {
+                let flow = self.settings.veh_flow(kind, flow_index).unwrap();
+                let series = &output.trajectory_series;
+                let approach = interaction::approach(&self.settings, flow, series);
+
+                // traffic keeps left, so the right turn crosses the opposing through lanes
+                // and holds for a gap while the left turn stays on the near side
+                let gap = match kind {
+                    AgentKind::RtVeh => {
+                        interaction::gap_acceptance(&self.settings, flow, series, &mut self.rng)
+                    }
+                    _ => None,
+                };
+                // both turns cross a crosswalk on the destination arm and yield to pedestrians
+                let crosswalks = match kind {
+                    AgentKind::LtVeh | AgentKind::RtVeh => interaction::crosswalk_crossings(
+                        &self.settings,
+                        flow,
+                        series,
+                        &mut self.rng,
+                    ),
+                    _ => vec![],
+                };
+                Some(interaction::VehMotion::new(
+                    output, approach, gap, crosswalks,
+                ))
+            }
+            AgentOutput::Ped(_) => None,
+        };
+
+        let agent = Agent {
+            id: self.next_id,
+            kind,
+            flow_index,
+            spawn_secs: self.elapsed_time,
+            step: 0,
+            history: vec![(self.elapsed_time, 0)],
+            output,
+            motion,
+        };
+        self.next_id += 1;
+
+        let dirs = self.flow_dirs(kind, flow_index);
+        self.flow_log.push_back((self.elapsed_time, kind, dirs));
+
+        self.agents.push(agent);
+    }
+
+    fn flow_dirs(&self, kind: AgentKind, flow_index: usize) -> [settings::Dir; 2] {
+        match kind {
+            AgentKind::StVeh => {
+                let flow = &self.settings.st_veh_flows[flow_index];
+                [flow.src_dir, flow.dst_dir]
+            }
+            AgentKind::LtVeh => {
+                let flow = &self.settings.lt_veh_flows[flow_index];
+                [flow.src_dir, flow.dst_dir]
+            }
+            AgentKind::RtVeh => {
+                let flow = &self.settings.rt_veh_flows[flow_index];
+                [flow.src_dir, flow.dst_dir]
+            }
+            AgentKind::Ped => {
+                let flow = &self.settings.ped_flows[flow_index];
+                [flow.src, flow.dst]
+            }
+            AgentKind::IgPed => {
+                let flow = &self.settings.ig_ped_flows[flow_index];
+                [flow.src, flow.dst]
+            }
+        }
+    }
+
+    // rates per second (not per hour) over the last window on the crosswalk of the flow
+    fn observed_flows(&self, flow: &settings::PedFlow) -> (f64, f64, f64) {
+        let mut lt_veh_count = 0;
+        let mut forward_ped_count = 0;
+        let mut backward_ped_count = 0;
+        for (_, kind, dirs) in &self.flow_log {
+            match kind {
+                AgentKind::LtVeh if arm(dirs[1]) == arm(flow.src) => lt_veh_count += 1,
+                AgentKind::Ped | AgentKind::IgPed if dirs == &[flow.src, flow.dst] => {
+                    forward_ped_count += 1
+                }
+                AgentKind::Ped | AgentKind::IgPed if dirs == &[flow.dst, flow.src] => {
+                    backward_ped_count += 1
+                }
+                _ => {}
+            }
+        }
+        (
+            lt_veh_count as f64 / FLOW_WINDOW,
+            forward_ped_count as f64 / FLOW_WINDOW,
+            backward_ped_count as f64 / FLOW_WINDOW,
+        )
     }
 
-    pub fn show_simulation_inside(&mut self, ui: &mut egui::Ui) {
+    pub fn show_simulation_inside(
+        &mut self,
+        ui: &mut egui::Ui,
+        overlay_fn: impl FnOnce(&mut egui_plot::PlotUi),
+    ) {
         let mut points = vec![];
-        for (trajectory, step) in &self.trajectories {
-            let point = egui_plot::Points::new(trajectory[*step])
+        for agent in &self.agents {
+            let point = egui_plot::Points::new(agent.position())
                 .color(egui::Color32::RED)
                 .radius(2.0);
             points.push(point);
         }
 
+        let mut lines = vec![];
+        let mut texts = vec![];
+        for i in 0..self.agents.len() {
+            for j in i + 1..self.agents.len() {
+                let (a, b) = (&self.agents[i], &self.agents[j]);
+                let Some(ttc) = conflict::compute_ttc(a, b) else {
+                    continue;
+                };
+                if ttc > conflict::WARNING_TTC {
+                    continue;
+                }
+
+                let (p0, p1) = (a.position(), b.position());
+                let line = egui_plot::Line::new(vec![p0, p1]).color(egui::Color32::LIGHT_RED);
+                lines.push(line);
+
+                let p = [(p0[0] + p1[0]) * 0.5, (p0[1] + p1[1]) * 0.5];
+                let text = egui_plot::Text::new(p.into(), format!("{ttc:.1}s"))
+                    .color(egui::Color32::LIGHT_RED);
+                texts.push(text);
+            }
+        }
+
         self.settings.show_simulation_inside(ui, |plot_ui| {
+            lines.into_iter().for_each(|v| plot_ui.line(v));
+            texts.into_iter().for_each(|v| plot_ui.text(v));
             points.into_iter().for_each(|v| plot_ui.points(v));
+            overlay_fn(plot_ui);
         });
     }
 
@@ -214,14 +865,39 @@ impl Forward {
         for i in 0..self.settings.veh_signals.len() {
             let signal = &self.settings.veh_signals[i];
             let layer = i as f64;
-            let intime = self.elapsed_time % signal.cycle_secs;
+            let intime = match &self.controller {
+                None => self.elapsed_time % signal.cycle_secs,
+                // actuated state placed on the planned bars
+                Some(_) => {
+                    let key = [signal.src_dir, signal.dst_dir];
+                    let secs = self.veh_signal_secs.get(&key).copied().unwrap_or(0.0);
+                    let start = match self.veh_signals.get(&key) {
+                        Some(VehSignalState::Green | VehSignalState::GreenArrow) | None => 0.0,
+                        Some(VehSignalState::Yellow) => signal.green_secs,
+                        Some(VehSignalState::Red) => signal.green_secs + signal.yellow_secs,
+                    };
+                    (signal.offset_secs + start + secs) % signal.cycle_secs
+                }
+            };
             points.push([intime, layer]);
         }
 
         for i in 0..self.settings.ped_signals.len() {
             let signal = &self.settings.ped_signals[i];
             let layer = (self.settings.veh_signals.len() + i) as f64;
-            let intime = self.elapsed_time % signal.cycle_secs;
+            let intime = match &self.controller {
+                None => self.elapsed_time % signal.cycle_secs,
+                Some(_) => {
+                    let key = [signal.src_dir, signal.dst_dir];
+                    let secs = self.ped_signal_secs.get(&key).copied().unwrap_or(0.0);
+                    let start = match self.ped_signals.get(&key) {
+                        Some(PedSignalState::Green) | None => 0.0,
+                        Some(PedSignalState::Blink) => signal.green_secs,
+                        Some(PedSignalState::Red) => signal.green_secs + signal.blink_secs,
+                    };
+                    (signal.offset_secs + start + secs) % signal.cycle_secs
+                }
+            };
             points.push([intime, layer]);
         }
 
@@ -231,3 +907,201 @@ impl Forward {
         });
     }
 }
+
+fn arm(dir: settings::Dir) -> [settings::Dir; 2] {
+    match dir {
+        settings::Dir::NxPy | settings::Dir::NxNy => [settings::Dir::NxPy, settings::Dir::NxNy],
+        settings::Dir::PxPy | settings::Dir::PxNy => [settings::Dir::PxPy, settings::Dir::PxNy],
+        settings::Dir::NyNx | settings::Dir::NyPx => [settings::Dir::NyNx, settings::Dir::NyPx],
+        settings::Dir::PyNx | settings::Dir::PyPx => [settings::Dir::PyNx, settings::Dir::PyPx],
+    }
+}
+
+#[cfg(test)]
+impl Forward {
+    // an agent sampled from the flow's model, moved onto the path when given
+    pub fn spawn_sampled(
+        &mut self,
+        kind: AgentKind,
+        flow_index: usize,
+        path: Option<Vec<[f64; 2]>>,
+    ) -> &mut Agent {
+        let settings = &self.settings;
+        let model = &self.model;
+        let rng = &mut self.rng;
+        let context = compute::PedContext::default();
+        let output = std::iter::repeat_with(|| match kind {
+            AgentKind::StVeh => {
+                let flow = &settings.st_veh_flows[flow_index];
+                compute::compute_st_veh(settings, flow, rng).map(AgentOutput::Veh)
+            }
+            AgentKind::LtVeh => {
+                let flow = &settings.lt_veh_flows[flow_index];
+                compute::compute_lt_veh(settings, &model.lt_veh, flow, rng)
+                    .map(compute::extend_upstream)
+                    .map(AgentOutput::Veh)
+            }
+            AgentKind::RtVeh => {
+                let flow = &settings.rt_veh_flows[flow_index];
+                compute::compute_rt_veh(settings, &model.rt_veh, flow, rng)
+                    .map(compute::extend_upstream)
+                    .map(AgentOutput::Veh)
+            }
+            AgentKind::Ped => {
+                let flow = &settings.ped_flows[flow_index];
+                compute::compute_ped(settings, &model.ped, flow, &context, rng)
+                    .map(AgentOutput::Ped)
+            }
+            AgentKind::IgPed => {
+                let flow = &settings.ig_ped_flows[flow_index];
+                compute::compute_ig_ped(settings, &model.ig_ped, flow, &context, rng)
+                    .map(AgentOutput::Ped)
+            }
+        });
+        let mut output = output.flatten().next().unwrap();
+        match (&mut output, path) {
+            (AgentOutput::Veh(output), Some(path)) => output.trajectory_series = path,
+            (AgentOutput::Ped(output), Some(path)) => output.trajectory_series = path,
+            _ => {}
+        }
+        self.spawn(kind, flow_index, output);
+        self.agents.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every agent spawned over 20 sec, exited or not, printed since unset terms are NaN
+    fn run(seed: u64) -> Vec<String> {
+        let settings = settings::Settings {
+            seed,
+            ..Default::default()
+        };
+        let mut forward = Forward::new(settings).unwrap();
+        let mut agents = vec![];
+        for _ in 0..200 {
+            forward.forward(0.1);
+            agents.extend(forward.drain_archive());
+        }
+        agents.extend(forward.agents);
+        agents.iter().map(|v| format!("{v:?}")).collect()
+    }
+
+    #[test]
+    fn same_seed_same_trajectories() {
+        let (a, b) = (run(7), run(7));
+        assert!(!a.is_empty());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn other_seed_other_trajectories() {
+        let (a, b) = (run(7), run(8));
+        assert_ne!(a, b);
+    }
+
+    // a through vehicle reaching its stop line at the given signal state
+    fn crossing_stop_line(state: VehSignalState) -> Vec<interaction::RedLightEvent> {
+        let mut forward = Forward::new(Default::default()).unwrap();
+        let flow = forward.settings.st_veh_flows[0].clone();
+        let key = [flow.src_dir, flow.dst_dir];
+        let signals = &forward.settings.veh_signals;
+        let signal = signals
+            .iter()
+            .find(|v| [v.src_dir, v.dst_dir] == key)
+            .unwrap();
+        let secs = (0..240)
+            .map(|v| v as f64)
+            .find(|&v| signal.state_at(v).0 == state);
+        forward.elapsed_time = secs.unwrap();
+
+        let agent = forward.spawn_sampled(AgentKind::StVeh, 0, None);
+        let motion = agent.motion.as_mut().unwrap();
+        let stop_distance = motion.approach.as_ref().unwrap().stop_distance.unwrap();
+        motion.distance = stop_distance - 0.01;
+        motion.speed = 10.0;
+        agent.step = motion.step_at(0);
+
+        forward.forward(0.01);
+        forward.red_light_events
+    }
+
+    #[test]
+    fn red_light_running_only_on_red() {
+        let events = crossing_stop_line(VehSignalState::Red);
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            (events[0].veh_id, events[0].veh_kind),
+            (0, AgentKind::StVeh)
+        );
+        assert!(crossing_stop_line(VehSignalState::Green).is_empty());
+    }
+
+    #[test]
+    fn actuated_signal_outside_stages_stays_red() {
+        let settings = settings::Settings {
+            control: settings::ControlMode::Actuated,
+            ..Default::default()
+        };
+        let mut forward = Forward::new(settings).unwrap();
+        let veh_key = forward.controller.as_ref().unwrap().stages[0].veh_keys[0];
+        let ped_key = forward.controller.as_ref().unwrap().stages[0].ped_keys[0];
+        for stage in &mut forward.controller.as_mut().unwrap().stages {
+            stage.veh_keys.retain(|&v| v != veh_key);
+            stage.ped_keys.retain(|&v| v != ped_key);
+        }
+        for _ in 0..100 {
+            forward.forward(0.1);
+            assert_eq!(forward.veh_signals[&veh_key], VehSignalState::Red);
+            assert_eq!(forward.ped_signals[&ped_key], PedSignalState::Red);
+        }
+        assert!((forward.veh_signal_secs[&veh_key] - forward.elapsed_time).abs() < 0.2);
+    }
+
+    #[test]
+    fn veh_state_wraps_on_cycle() {
+        let signal = settings::VehSignal {
+            offset_secs: 30.0,
+            ..Default::default()
+        };
+        assert_eq!(signal.state_at(30.0), (VehSignalState::Green, 0.0));
+        assert_eq!(signal.state_at(142.0), (VehSignalState::Yellow, 2.0));
+        assert_eq!(signal.state_at(150.0), (VehSignalState::Red, 5.0));
+        assert_eq!(signal.state_at(270.0), (VehSignalState::Green, 0.0));
+        assert_eq!(signal.state_at(10.0), (VehSignalState::Red, 105.0));
+    }
+
+    #[test]
+    fn veh_state_keeps_cycle_with_mismatched_red() {
+        // green, yellow and red sum to 200 sec, the cycle still sets the period
+        let signal = settings::VehSignal {
+            red_secs: 85.0,
+            ..Default::default()
+        };
+        assert_eq!(signal.state_at(200.0), (VehSignalState::Red, 85.0));
+        assert_eq!(signal.state_at(240.0), (VehSignalState::Green, 0.0));
+    }
+
+    #[test]
+    fn veh_state_shows_arrow() {
+        let signal = settings::VehSignal {
+            turn_mode: settings::TurnMode::LaggingProtectedPermissive,
+            ..Default::default()
+        };
+        assert_eq!(signal.state_at(99.0).0, VehSignalState::Green);
+        assert_eq!(signal.state_at(100.0).0, VehSignalState::GreenArrow);
+        assert_eq!(signal.state_at(345.0).0, VehSignalState::GreenArrow);
+        assert_eq!(signal.state_at(110.0).0, VehSignalState::Yellow);
+    }
+
+    #[test]
+    fn ped_state_wraps_on_cycle() {
+        let signal = settings::PedSignal::default();
+        assert_eq!(signal.state_at(120.0), (PedSignalState::Green, 0.0));
+        assert_eq!(signal.state_at(225.0), (PedSignalState::Blink, 5.0));
+        assert_eq!(signal.state_at(0.0), (PedSignalState::Red, 10.0));
+        assert_eq!(signal.state_at(-120.0), (PedSignalState::Green, 0.0));
+    }
+}
@@ -0,0 +1,566 @@
+use std::io::Write;
+
+use crate::{compute, settings};
+
+pub const UTC_OFFSET_SECS: f64 = 9.0 * 3600.0;
+pub const MIN_POINTS: usize = 3;
+// curvature of a vehicle standing still is noise
+pub const MIN_SPEED: f64 = 0.5;
+// movement section around the origin, covering the simulated inflow and outflow profiles
+pub const ENTRY_DISTANCE: f64 = 30.0;
+pub const EXIT_DISTANCE: f64 = 60.0;
+// headings over the first and last metres of the section
+pub const HEADING_DISTANCE: f64 = 5.0;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GpsPoint {
+    pub secs: f64,
+    pub x: f64,
+    pub y: f64,
+}
+
+// one observed run, series shaped like VehOutput
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Run {
+    pub file: String,
+    pub start_secs: f64,
+    pub v_in: f64,
+    pub v_out: f64,
+    pub c_in: f64,
+    pub c_out: f64,
+    pub v_min: f64,
+    pub t_min: f64,
+    pub x_min: f64,
+    pub r_min: f64,
+    pub t_exit: f64,
+    pub velocity_series: Vec<[f64; 2]>,
+    pub position_series: Vec<[f64; 2]>,
+    pub curvature_series: Vec<[f64; 2]>,
+    pub trajectory_series: Vec<[f64; 2]>,
+}
+
+// dt,x,y rows as written by the gps loggers, optionally with a header
+pub fn read_points(
+    path: &std::path::Path,
+    header: bool,
+    utc: bool,
+) -> std::io::Result<Vec<GpsPoint>> {
+    let text = std::fs::read_to_string(path)?;
+    let offset_secs = if utc { UTC_OFFSET_SECS } else { 0.0 };
+
+    let mut points = vec![];
+    for (i, line) in text.lines().enumerate().skip(header as usize) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let invalid = || {
+            let msg = format!("{}:{}: bad row {line}", path.display(), i + 1);
+            std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+        };
+        let [dt, x, y] = fields[..] else {
+            return Err(invalid());
+        };
+        let dt = humantime::parse_rfc3339_weak(dt).map_err(|_| invalid())?;
+        let secs = dt
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        points.push(GpsPoint {
+            secs: secs + offset_secs,
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+        });
+    }
+    Ok(points)
+}
+
+impl settings::GpsFrame {
+    pub fn to_local(&self, x: f64, y: f64) -> [f64; 2] {
+        let m = nalgebra::Rotation2::new(-self.rotation.to_radians());
+        let p = m * nalgebra::Vector2::new(x - self.origin_x, y - self.origin_y);
+        [p.x, p.y]
+    }
+}
+
+// cubic hermite through the points, tangents from neighbouring points
+fn hermite(
+    t: &[f64],
+    p: &[nalgebra::Vector2<f64>],
+    m: &[nalgebra::Vector2<f64>],
+    s: f64,
+) -> [nalgebra::Vector2<f64>; 3] {
+    let i = t.partition_point(|v| *v <= s).clamp(1, t.len() - 1) - 1;
+    let h = t[i + 1] - t[i];
+    let u = (s - t[i]) / h;
+    let (p0, p1, m0, m1) = (p[i], p[i + 1], m[i] * h, m[i + 1] * h);
+
+    let pos = p0 * (2.0 * u.powi(3) - 3.0 * u.powi(2) + 1.0)
+        + m0 * (u.powi(3) - 2.0 * u.powi(2) + u)
+        + p1 * (-2.0 * u.powi(3) + 3.0 * u.powi(2))
+        + m1 * (u.powi(3) - u.powi(2));
+    let vel = (p0 * (6.0 * u.powi(2) - 6.0 * u)
+        + m0 * (3.0 * u.powi(2) - 4.0 * u + 1.0)
+        + p1 * (-6.0 * u.powi(2) + 6.0 * u)
+        + m1 * (3.0 * u.powi(2) - 2.0 * u))
+        / h;
+    let acc = (p0 * (12.0 * u - 6.0)
+        + m0 * (6.0 * u - 4.0)
+        + p1 * (-12.0 * u + 6.0)
+        + m1 * (6.0 * u - 2.0))
+        / h.powi(2);
+    [pos, vel, acc]
+}
+
+impl Run {
+    pub fn new(file: String, frame: &settings::GpsFrame, points: &[GpsPoint]) -> Option<Self> {
+        // loggers repeat timestamps at one second resolution, spread the repeats evenly
+        // up to the next timestamp within the second
+        let groups: Vec<_> = points.chunk_by(|a, b| a.secs == b.secs).collect();
+        let mut t: Vec<f64> = vec![];
+        let mut p = vec![];
+        let mut dropped = 0;
+        for (i, group) in groups.iter().enumerate() {
+            let secs = group[0].secs;
+            let span = groups
+                .get(i + 1)
+                .map_or(1.0, |v| (v[0].secs - secs).clamp(0.0, 1.0));
+            for (j, point) in group.iter().enumerate() {
+                let secs = secs + span * j as f64 / group.len() as f64;
+                if t.last().is_some_and(|v| secs <= *v) {
+                    dropped += 1;
+                    continue;
+                }
+                t.push(secs);
+                p.push(nalgebra::Vector2::from(frame.to_local(point.x, point.y)));
+            }
+        }
+        if dropped > 0 {
+            log::warn!("{file}: dropped {dropped} points out of time order");
+        }
+        Self::from_track(file, &t, &p)
+    }
+
+    // timed points of a track in the local frame
+    pub fn from_track(file: String, t: &[f64], p: &[nalgebra::Vector2<f64>]) -> Option<Self> {
+        if t.len() < MIN_POINTS {
+            return None;
+        }
+
+        let n = t.len();
+        let m: Vec<_> = (0..n)
+            .map(|i| {
+                let (j, k) = (i.saturating_sub(1), usize::min(i + 1, n - 1));
+                (p[k] - p[j]) / (t[k] - t[j])
+            })
+            .collect();
+
+        let mut run = Self {
+            file,
+            start_secs: t[0],
+            ..Default::default()
+        };
+        let steps = ((t[n - 1] - t[0]) / compute::STEP).floor() as usize;
+        let mut distance = 0.0;
+        for k in 0..=steps {
+            let time = k as f64 * compute::STEP;
+            let [pos, vel, acc] = hermite(t, p, &m, t[0] + time);
+            let speed = vel.norm();
+            let curvature = match speed < MIN_SPEED {
+                true => 0.0,
+                false => (vel.x * acc.y - vel.y * acc.x) / speed.powi(3),
+            };
+            if k > 0 {
+                let [_, v0] = run.velocity_series[k - 1];
+                distance += (v0 + speed) * 0.5 * compute::STEP;
+            }
+            run.velocity_series.push([time, speed]);
+            run.position_series.push([time, distance]);
+            run.curvature_series.push([distance, curvature]);
+            run.trajectory_series.push([pos.x, pos.y]);
+        }
+
+        // origin at the closest approach to the intersection centre
+        let norm = |v: &[f64; 2]| v[0].hypot(v[1]);
+        let o = (0..run.trajectory_series.len())
+            .min_by(|&a, &b| {
+                norm(&run.trajectory_series[a]).total_cmp(&norm(&run.trajectory_series[b]))
+            })
+            .unwrap();
+        let [t_o, x_o] = run.position_series[o];
+        for [t, x] in run.position_series.iter_mut() {
+            *t -= t_o;
+            *x -= x_o;
+        }
+        for [t, _] in run.velocity_series.iter_mut() {
+            *t -= t_o;
+        }
+        for [x, _] in run.curvature_series.iter_mut() {
+            *x -= x_o;
+        }
+
+        // trim to the movement section, v_in and v_out are taken at its ends
+        let section = |k: &usize| {
+            let [_, x] = run.position_series[*k];
+            (-ENTRY_DISTANCE..=EXIT_DISTANCE).contains(&x)
+        };
+        let i0 = (0..=o).rev().take_while(section).last().unwrap_or(o);
+        let i1 = (o..run.position_series.len())
+            .take_while(section)
+            .last()
+            .unwrap_or(o);
+        for series in [
+            &mut run.velocity_series,
+            &mut run.position_series,
+            &mut run.curvature_series,
+            &mut run.trajectory_series,
+        ] {
+            series.truncate(i1 + 1);
+            series.drain(..i0);
+        }
+        if run.velocity_series.len() < MIN_POINTS {
+            return None;
+        }
+
+        let i_min = (0..run.velocity_series.len())
+            .min_by(|&a, &b| run.velocity_series[a][1].total_cmp(&run.velocity_series[b][1]))
+            .unwrap();
+        run.v_min = run.velocity_series[i_min][1];
+        run.t_min = run.velocity_series[i_min][0];
+        run.x_min = run.position_series[i_min][1];
+
+        // cubic speed profiles through the minimum, as in compute_lt_veh
+        let [t_start, v_in] = run.velocity_series[0];
+        let [t_end, v_out] = *run.velocity_series.last().unwrap();
+        run.v_in = v_in;
+        run.v_out = v_out;
+        run.c_in = 2.0 * (v_in - run.v_min) / (run.t_min - t_start).powi(3);
+        run.c_out = 2.0 * (v_out - run.v_min) / (t_end - run.t_min).powi(3);
+        run.t_exit = section_secs(&run.velocity_series, &run.position_series);
+        let c_max = run
+            .curvature_series
+            .iter()
+            .map(|v| v[1].abs())
+            .fold(0.0, f64::max);
+        run.r_min = c_max.recip();
+        Some(run)
+    }
+}
+
+// seconds to cover the movement section, at the end speeds beyond the profile
+pub fn section_secs(velocity_series: &[[f64; 2]], position_series: &[[f64; 2]]) -> f64 {
+    let (Some(&[_, v0]), Some(&[_, v1])) = (velocity_series.first(), velocity_series.last()) else {
+        return f64::NAN;
+    };
+    let (Some(&[t0, x0]), Some(&[t1, x1])) = (position_series.first(), position_series.last())
+    else {
+        return f64::NAN;
+    };
+    let secs_at = |x: f64| {
+        if x <= x0 {
+            return t0 - (x0 - x) / v0;
+        }
+        if x >= x1 {
+            return t1 + (x - x1) / v1;
+        }
+        let i = position_series.partition_point(|v| v[1] < x);
+        let [ta, xa] = position_series[i - 1];
+        let [tb, xb] = position_series[i];
+        ta + (tb - ta) * (x - xa) / (xb - xa)
+    };
+    secs_at(EXIT_DISTANCE) - secs_at(-ENTRY_DISTANCE)
+}
+
+// road axis, width and hn of the arm a point lies on
+fn arm(
+    settings: &settings::Settings,
+    p: nalgebra::Vector2<f64>,
+) -> (nalgebra::Vector2<f64>, f64, f64) {
+    let along = nalgebra::Vector2::x();
+    let across = nalgebra::Rotation2::new(settings.angle.to_radians()) * along;
+    let lateral = |axis: nalgebra::Vector2<f64>| axis.perp(&p).abs();
+    match lateral(along) <= lateral(across) {
+        true => (along, settings.width_along, settings.hn_along),
+        false => (across, settings.width_across, settings.hn_across),
+    }
+}
+
+// movement covariates of the turning models, from the track and the intersection
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Geometry {
+    pub angle: f64,
+    pub padding_out: f64,
+    pub hn_in: f64,
+    pub hn_out: f64,
+}
+
+impl Run {
+    pub fn geometry(&self, settings: &settings::Settings) -> Geometry {
+        let series = &self.trajectory_series;
+        let [_, x_start] = self.position_series[0];
+        let [_, x_end] = *self.position_series.last().unwrap();
+        let i = self
+            .position_series
+            .iter()
+            .position(|v| v[1] >= x_start + HEADING_DISTANCE)
+            .unwrap_or(series.len() - 1);
+        let j = self
+            .position_series
+            .iter()
+            .rposition(|v| v[1] <= x_end - HEADING_DISTANCE)
+            .unwrap_or(0);
+        let point = |k: usize| nalgebra::Vector2::from(series[k]);
+        let heading_in = point(i) - point(0);
+        let heading_out = point(series.len() - 1) - point(j);
+
+        let p_in = point(0);
+        let p_out = point(series.len() - 1);
+        let (_, _, hn_in) = arm(settings, p_in);
+        let (axis, width, hn_out) = arm(settings, p_out);
+        Geometry {
+            angle: heading_in.angle(&heading_out).to_degrees(),
+            padding_out: width * 0.5 - axis.perp(&p_out).abs(),
+            hn_in,
+            hn_out,
+        }
+    }
+}
+
+// one run per file, as preprocess segments uploads by filename
+pub fn import(
+    paths: &[std::path::PathBuf],
+    frame: &settings::GpsFrame,
+    header: bool,
+    utc: bool,
+) -> std::io::Result<Vec<Run>> {
+    let mut runs = vec![];
+    for path in paths {
+        let points = read_points(path, header, utc)?;
+        let file = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        match Run::new(file, frame, &points) {
+            Some(run) => runs.push(run),
+            None => log::warn!("{}: too few points for a run", path.display()),
+        }
+    }
+    Ok(runs)
+}
+
+fn format_secs(secs: f64) -> String {
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs_f64(secs);
+    let text = humantime::format_rfc3339_seconds(time).to_string();
+    text.trim_end_matches('Z').replace('T', " ")
+}
+
+// the columns calibrate and report read
+pub fn write_runs(
+    settings: &settings::Settings,
+    runs: &[Run],
+    kind: &str,
+    large: bool,
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    writeln!(
+        writer,
+        "id,kind,file,start,duration,v_in,v_out,padding_out,angle,radius,large,hn_in,hn_out,\
+         c_in,c_out,v_min,t_min,x_min,r_min,t_exit"
+    )?;
+    for (id, run) in runs.iter().enumerate() {
+        // samples span one step less than their count
+        let duration = run.velocity_series.len().saturating_sub(1) as f64 * compute::STEP;
+        let geometry = run.geometry(settings);
+        writeln!(
+            writer,
+            "{id},{kind},{},{},{duration},{},{},{},{},{},{large},{},{},{},{},{},{},{},{},{}",
+            run.file,
+            format_secs(run.start_secs),
+            run.v_in,
+            run.v_out,
+            geometry.padding_out,
+            geometry.angle,
+            settings.radius,
+            geometry.hn_in,
+            geometry.hn_out,
+            run.c_in,
+            run.c_out,
+            run.v_min,
+            run.t_min,
+            run.x_min,
+            run.r_min,
+            run.t_exit
+        )?;
+    }
+
+    writer.flush()
+}
+
+pub fn write_profiles(runs: &[Run], kind: &str, path: &std::path::Path) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    writeln!(writer, "id,kind,t,velocity,x,curvature,px,py")?;
+    for (id, run) in runs.iter().enumerate() {
+        for step in 0..run.velocity_series.len() {
+            let [t, velocity] = run.velocity_series[step];
+            let [x, curvature] = run.curvature_series[step];
+            let [px, py] = run.trajectory_series[step];
+            writeln!(
+                writer,
+                "{id},{kind},{t},{velocity},{x},{curvature},{px},{py}"
+            )?;
+        }
+    }
+
+    writer.flush()
+}
+
+pub fn run(
+    settings: &settings::Settings,
+    paths: &[std::path::PathBuf],
+    kind: &str,
+    header: bool,
+    utc: bool,
+    large: bool,
+    output_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    let runs = import(paths, &settings.gps_frame, header, utc)?;
+
+    std::fs::create_dir_all(output_dir)?;
+    write_runs(settings, &runs, kind, large, &output_dir.join("runs.csv"))?;
+    write_profiles(&runs, kind, &output_dir.join("profiles.csv"))?;
+
+    log::info!(
+        "imported {} runs from {} files into {}",
+        runs.len(),
+        paths.len(),
+        output_dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_points_with_header_and_utc() {
+        let path = std::env::temp_dir().join(format!("gps-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "dt,x,y\n2024-09-17 08:00:00,1.5,-2.5\n\n2024-09-17 08:00:01,2,-3\n",
+        )
+        .unwrap();
+        let local = read_points(&path, true, false);
+        let utc = read_points(&path, true, true);
+        let headless = read_points(&path, false, false);
+        std::fs::remove_file(&path).unwrap();
+
+        let local = local.unwrap();
+        assert_eq!(local.len(), 2);
+        assert_eq!(
+            local[0],
+            GpsPoint {
+                secs: 1_726_560_000.0,
+                x: 1.5,
+                y: -2.5
+            }
+        );
+        assert_eq!(local[1].secs, 1_726_560_001.0);
+        assert_eq!(utc.unwrap()[0].secs, 1_726_560_000.0 + UTC_OFFSET_SECS);
+        // the header is a bad row when not skipped
+        assert!(headless.is_err());
+    }
+
+    #[test]
+    fn hermite_exact_on_linear_motion() {
+        let t = [0.0, 1.0, 3.0, 4.0];
+        let p: Vec<_> = t
+            .iter()
+            .map(|&t| nalgebra::Vector2::new(2.0 * t - 1.0, 0.5 * t))
+            .collect();
+        let m = vec![nalgebra::Vector2::new(2.0, 0.5); t.len()];
+
+        for s in [0.0, 0.5, 1.0, 2.25, 4.0] {
+            let [pos, vel, acc] = hermite(&t, &p, &m, s);
+            assert!((pos - nalgebra::Vector2::new(2.0 * s - 1.0, 0.5 * s)).norm() < 1e-12);
+            assert!((vel - nalgebra::Vector2::new(2.0, 0.5)).norm() < 1e-12);
+            assert!(acc.norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn new_spreads_repeated_timestamps() {
+        // two fixes a second at 10 m/s, logged with whole seconds
+        let points: Vec<_> = (0..8)
+            .map(|k| GpsPoint {
+                secs: 100.0 + (k / 2) as f64,
+                x: -20.0 + 5.0 * k as f64,
+                y: 0.0,
+            })
+            .collect();
+        let run = Run::new("track".to_string(), &Default::default(), &points).unwrap();
+        let [t0, _] = run.velocity_series[0];
+        let [t1, _] = *run.velocity_series.last().unwrap();
+        assert!((t1 - t0 - 3.5).abs() < 0.05);
+        assert!(run
+            .velocity_series
+            .iter()
+            .all(|v| (v[1] - 10.0).abs() < 0.1));
+    }
+
+    #[test]
+    fn section_secs_extends_at_end_speeds() {
+        let velocity = [[-1.0, 10.0], [0.0, 5.0], [1.0, 20.0]];
+        let position = [[-1.0, -10.0], [0.0, 0.0], [1.0, 10.0]];
+        assert_eq!(section_secs(&velocity, &position), 2.0 + 2.0 + 2.5);
+        assert!(section_secs(&[], &[]).is_nan());
+    }
+
+    // the cubic inflow and outflow of compute_lt_veh, on a straight track through the centre
+    #[test]
+    fn from_track_recovers_cubic_profile() {
+        let (v_in, v_min, v_out) = (10.0, 4.0, 10.0);
+        let (t_min, t_next) = (4.0, 5.0);
+        let c_in = 2.0 * (v_in - v_min) / f64::powi(t_min, 3);
+        let c_out = 2.0 * (v_out - v_min) / f64::powi(t_next, 3);
+        let x_min = c_in * f64::powi(t_min, 4) / 4.0 - c_in * t_min * f64::powi(t_min, 3) / 2.0
+            + v_in * t_min;
+        let x = |t: f64| match t <= t_min {
+            true => c_in * t.powi(4) / 4.0 - c_in * t_min * t.powi(3) / 2.0 + v_in * t - x_min,
+            false => {
+                let t = t - t_min;
+                -c_out * t.powi(4) / 4.0 + c_out * t_next * t.powi(3) / 2.0 + v_min * t
+            }
+        };
+
+        let t: Vec<_> = (0..=90).map(|k| 100.0 + k as f64 * 0.1).collect();
+        let p: Vec<_> = t
+            .iter()
+            .map(|v| nalgebra::Vector2::new(x(v - 100.0), 0.0))
+            .collect();
+        let run = Run::from_track("track".to_string(), &t, &p).unwrap();
+
+        assert_eq!(run.start_secs, 100.0);
+        assert!((run.v_in - v_in).abs() < 0.1);
+        assert!((run.v_out - v_out).abs() < 0.1);
+        assert!((run.v_min - v_min).abs() < 0.05);
+        // the speed is flat around its minimum, so its time is the loosest
+        assert!(run.t_min.abs() < 0.1);
+        assert!(run.x_min.abs() < 0.1 * v_min);
+        assert!((run.c_in / c_in - 1.0).abs() < 0.05);
+        assert!((run.c_out / c_out - 1.0).abs() < 0.05);
+        // the profile is shorter than the section, its ends are crossed at v_in and v_out
+        let t_exit = t_min
+            + t_next
+            + (ENTRY_DISTANCE - x_min) / v_in
+            + (EXIT_DISTANCE - x(t_min + t_next)) / v_out;
+        assert!((run.t_exit - t_exit).abs() < 0.05);
+        assert!(run.r_min.is_infinite());
+    }
+}
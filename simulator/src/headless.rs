@@ -0,0 +1,76 @@
+use crate::{compute, export, forward, settings, validator};
+
+pub fn run(
+    settings: settings::Settings,
+    duration_secs: f64,
+    step_secs: f64,
+    output_dir: &std::path::Path,
+    fcd: bool,
+) -> std::io::Result<()> {
+    if step_secs < compute::STEP {
+        let msg = format!("timestep must be at least {} sec", compute::STEP);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    }
+
+    let mut forward = forward::Forward::new(settings)?;
+
+    std::fs::create_dir_all(output_dir)?;
+
+    let violations = validator::validate_signals(&forward.settings);
+    if !violations.is_empty() {
+        for violation in &violations {
+            log::error!("{violation}");
+        }
+        let msg = format!("signal settings have {} violations", violations.len());
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    }
+
+    // exited agents are written as the run goes, rows come in exit order
+    let mut exporter = export::Exporter::create(output_dir, fcd)?;
+    let steps = (duration_secs / step_secs).round() as usize;
+    for _ in 0..steps {
+        forward.forward(step_secs);
+        exporter.write(&forward.drain_archive())?;
+        let archive_secs = forward.archive.iter().map(|v| v.spawn_secs);
+        let spawn_secs = forward.agents.iter().map(|v| v.spawn_secs);
+        let secs = archive_secs
+            .chain(spawn_secs)
+            .fold(forward.elapsed_time, f64::min);
+        exporter.flush_fcd(secs)?;
+    }
+    forward.flush_pet_events();
+    exporter.write(&forward.archive)?;
+    exporter.write(&forward.agents)?;
+    exporter.finish()?;
+    export::write_pet_events(&forward.pet_events, &output_dir.join("pet.csv"))?;
+    export::write_ttc_records(&forward.ttc_records, &output_dir.join("ttc.csv"))?;
+    export::write_yield_events(&forward.yield_events, &output_dir.join("yield.csv"))?;
+    export::write_red_light_events(&forward.red_light_events, &output_dir.join("red_light.csv"))?;
+    // scenario as simulated, with the model version used
+    forward.settings.save(&output_dir.join("scenario.toml"))?;
+
+    log::info!(
+        "simulated {:.1} sec in {steps} steps into {}",
+        forward.elapsed_time,
+        output_dir.display()
+    );
+    if let Some(arrival_secs) = forward
+        .pending
+        .iter()
+        .map(|v| v.arrival_secs)
+        .reduce(f64::min)
+    {
+        log::warn!(
+            "{} vehicles still waiting to enter, the longest for {:.1} sec",
+            forward.pending.len(),
+            forward.elapsed_time - arrival_secs
+        );
+    }
+    let mut spillback: Vec<_> = forward.spillback.iter().collect();
+    spillback.sort_by_key(|((dir, lane), _)| (*dir as usize, *lane));
+    for ((dir, lane), count) in spillback {
+        log::warn!("{dir:?} lane {lane}: {count} vehicles spilled back out of the approach");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,777 @@
+use nalgebra::*;
+
+use crate::{compute, forward, settings};
+
+pub const MAX_ACCEL: f64 = 1.5;
+pub const COMFORT_DECEL: f64 = 2.0;
+pub const MIN_GAP: f64 = 2.0;
+pub const TIME_HEADWAY: f64 = 1.5;
+pub const MAX_DECEL: f64 = 6.0;
+pub const VEH_LENGTH: f64 = 5.0;
+
+const CONFLICT_MARGIN: f64 = 3.0;
+const DECISION_DISTANCE: f64 = 10.0;
+const YIELD_DISTANCE: f64 = 20.0;
+const YIELD_LATERAL: f64 = 4.0;
+const PED_MARGIN: f64 = 0.5;
+const LANE_TOLERANCE: f64 = 1.0;
+const YELLOW_STOP_TIME: f64 = 3.0;
+const YELLOW_STOP_SLOPE: f64 = 2.0;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct VehMotion {
+    pub distance: f64,
+    pub speed: f64,
+    pub arc_lengths: Vec<f64>,
+    pub approach: Option<Approach>,
+    pub gap: Option<GapAcceptance>,
+    pub crosswalks: Vec<CrosswalkCrossing>,
+    pub leader: Option<(f64, f64)>,
+    pub stop: bool,
+    pub go: Option<bool>,
+    pub protected: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Approach {
+    pub lane: (settings::Dir, usize),
+    pub axis: Rotation2<f64>,
+    pub sign: f64,
+    pub lateral: f64,
+    pub stop_distance: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GapAcceptance {
+    pub critical_gap: f64,
+    pub hold_distance: f64,
+    pub axis: Rotation2<f64>,
+    pub sign: f64,
+    pub conflicts: Vec<(usize, f64)>,
+    pub accepted: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CrosswalkCrossing {
+    pub hold_distance: f64,
+    pub axis: Rotation2<f64>,
+    pub band: [f64; 2],
+    pub lateral: f64,
+    pub yields: bool,
+    pub decided: bool,
+    pub occupied: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RedLightEvent {
+    pub veh_id: usize,
+    pub veh_kind: forward::AgentKind,
+    pub veh_flow_index: usize,
+    pub secs: f64,
+    pub red_secs: f64,
+    pub point: [f64; 2],
+    pub speed: f64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct YieldEvent {
+    pub veh_id: usize,
+    pub veh_kind: forward::AgentKind,
+    pub veh_flow_index: usize,
+    pub ped_id: usize,
+    pub ped_kind: forward::AgentKind,
+    pub ped_flow_index: usize,
+    pub secs: f64,
+    pub point: [f64; 2],
+    pub distance: f64,
+    pub speed: f64,
+    pub yielded: bool,
+}
+
+impl VehMotion {
+    pub fn new(
+        output: &compute::VehOutput,
+        approach: Option<Approach>,
+        gap: Option<GapAcceptance>,
+        crosswalks: Vec<CrosswalkCrossing>,
+    ) -> Self {
+        Self {
+            distance: 0.0,
+            speed: output.velocity_series.first().map_or(0.0, |v| v[1]),
+            arc_lengths: arc_lengths(&output.trajectory_series),
+            approach,
+            gap,
+            crosswalks,
+            leader: None,
+            stop: false,
+            go: None,
+            protected: false,
+        }
+    }
+
+    pub fn last_distance(&self) -> f64 {
+        self.arc_lengths.last().copied().unwrap_or(0.0)
+    }
+
+    // obstacles ahead along the path as gap and speed
+    pub fn obstacles(&self) -> Vec<(f64, f64)> {
+        let mut obstacles = vec![];
+
+        // a permissive right turn waits for a gap in opposing through traffic, which it crosses
+        // under left-hand traffic, and both turns yield to pedestrians, an arrow lifts both
+        if !self.protected {
+            let gap = self.gap.iter().filter(|v| !v.accepted);
+            obstacles.extend(gap.map(|v| (v.hold_distance, 0.0)));
+
+            let crosswalks = self.crosswalks.iter();
+            let crosswalks = crosswalks.filter(|v| v.yields && v.decided && v.occupied);
+            obstacles.extend(crosswalks.map(|v| (v.hold_distance, 0.0)));
+        }
+
+        // stop line unless it is too late to brake
+        let stop_distance = self.approach.as_ref().and_then(|v| v.stop_distance);
+        if let Some(stop_distance) =
+            stop_distance.filter(|_| self.stop && can_stop(self, MAX_DECEL))
+        {
+            obstacles.push((stop_distance, 0.0));
+        }
+
+        obstacles.extend(self.leader);
+
+        obstacles
+            .into_iter()
+            .map(|(d, v)| (d - self.distance, v))
+            .filter(|(s, _)| *s > 0.0)
+            .collect()
+    }
+
+    pub fn advance(&mut self, profile_speed: f64, dt: f64) {
+        let profile_speed = profile_speed.max(0.1);
+        let v = self.speed;
+
+        // follow the sampled profile, recovering after a slowdown
+        let mut v_next = if v < profile_speed {
+            f64::min(profile_speed, v + MAX_ACCEL * dt)
+        } else {
+            profile_speed
+        };
+
+        // interaction term of the intelligent driver model, the profile covers the free road
+        for (s, v_l) in self.obstacles() {
+            let dv = v - v_l;
+            let s_star = MIN_GAP
+                + (v * TIME_HEADWAY + v * dv / (2.0 * (MAX_ACCEL * COMFORT_DECEL).sqrt())).max(0.0);
+            let a = MAX_ACCEL * (1.0 - (s_star / s.max(0.01)).powi(2));
+            v_next = f64::min(v_next, v + a * dt);
+        }
+
+        self.speed = v_next.max(0.0);
+        self.distance += self.speed * dt;
+
+        if let Some((d, v_l)) = &mut self.leader {
+            *d += *v_l * dt;
+        }
+    }
+
+    pub fn step_at(&self, step: usize) -> usize {
+        let mut step = step;
+        while step + 1 < self.arc_lengths.len() && self.arc_lengths[step + 1] <= self.distance {
+            step += 1;
+        }
+        step
+    }
+}
+
+// stop line ahead within reach at the given deceleration
+pub fn can_stop(motion: &VehMotion, decel: f64) -> bool {
+    let Some(stop_distance) = motion.approach.as_ref().and_then(|v| v.stop_distance) else {
+        return false;
+    };
+    let s = stop_distance - motion.distance;
+    s > 0.0 && s >= motion.speed.powi(2) / (2.0 * decel)
+}
+
+// stop or go at the onset of yellow from the time to reach the stop line
+pub fn decide_go(motion: &VehMotion, rng: &mut impl rand::Rng) -> bool {
+    if !can_stop(motion, MAX_DECEL) {
+        return true;
+    }
+
+    let stop_distance = motion.approach.as_ref().and_then(|v| v.stop_distance);
+    let s = stop_distance.unwrap() - motion.distance;
+    let ttsl = s / motion.speed.max(0.1);
+    let stop_prob = 1.0 / (1.0 + f64::exp(-YELLOW_STOP_SLOPE * (ttsl - YELLOW_STOP_TIME)));
+    let distr = rand_distr::Uniform::new(0.0, 1.0);
+    rand::Rng::sample(rng, distr) >= stop_prob
+}
+
+// axis of an inbound approach, its travel sign, lane offsets and stop-line setback
+fn approach_axis(
+    settings: &settings::Settings,
+    dir: settings::Dir,
+) -> Option<(Rotation2<f64>, f64, &[f64], f64)> {
+    let m = Rotation2::new(settings.angle.to_radians());
+    let along = (&settings.lane_along[..], settings.sl_setback_along);
+    let across = (&settings.lane_across[..], settings.sl_setback_across);
+    let (axis, sign, (lanes, sl_setback)) = match dir {
+        settings::Dir::NxPy => (Rotation2::identity(), 1.0, along),
+        settings::Dir::PxNy => (Rotation2::identity(), -1.0, along),
+        settings::Dir::NyNx => (m, 1.0, across),
+        settings::Dir::PyPx => (m, -1.0, across),
+        _ => return None,
+    };
+    Some((axis, sign, lanes, sl_setback))
+}
+
+pub fn approach(
+    settings: &settings::Settings,
+    flow: &settings::VehFlow,
+    trajectory_series: &[[f64; 2]],
+) -> Option<Approach> {
+    let (axis, sign, lanes, sl_setback) = approach_axis(settings, flow.src_dir)?;
+
+    let arc_lengths = arc_lengths(trajectory_series);
+
+    // first point of the path past the stop line
+    let point = trajectory_series.iter().position(|p| {
+        let p = axis.inverse() * Point2::from(*p);
+        sign * p.x >= -sl_setback
+    });
+    let stop_distance = point.filter(|v| *v > 0).map(|j| arc_lengths[j]);
+
+    Some(Approach {
+        lane: (flow.src_dir, flow.src_lane),
+        axis,
+        sign,
+        lateral: lanes[flow.src_lane],
+        stop_distance,
+    })
+}
+
+// only right turns hold for a gap, under left-hand traffic they cross the opposing through lanes
+pub fn gap_acceptance(
+    settings: &settings::Settings,
+    flow: &settings::VehFlow,
+    trajectory_series: &[[f64; 2]],
+    rng: &mut impl rand::Rng,
+) -> Option<GapAcceptance> {
+    // opposing approach and the axis it travels on
+    let opposing_dir = match flow.src_dir {
+        settings::Dir::NxPy => settings::Dir::PxNy,
+        settings::Dir::PxNy => settings::Dir::NxPy,
+        settings::Dir::NyNx => settings::Dir::PyPx,
+        settings::Dir::PyPx => settings::Dir::NyNx,
+        _ => return None,
+    };
+    let (axis, sign, lanes, _) = approach_axis(settings, opposing_dir)?;
+
+    let arc_lengths = arc_lengths(trajectory_series);
+
+    // first point of the path entering each opposing through lane
+    let mut hold_distance = f64::INFINITY;
+    let mut conflicts = vec![];
+    for (i, st_flow) in settings.st_veh_flows.iter().enumerate() {
+        if st_flow.src_dir != opposing_dir {
+            continue;
+        }
+        let y = lanes[st_flow.src_lane];
+        let point = trajectory_series.iter().position(|p| {
+            let p = axis.inverse() * Point2::from(*p);
+            (p.y - y).abs() < CONFLICT_MARGIN
+        });
+        let Some(j) = point else {
+            continue;
+        };
+        let p = axis.inverse() * Point2::from(trajectory_series[j]);
+        hold_distance = hold_distance.min(arc_lengths[j]);
+        conflicts.push((i, p.x));
+    }
+
+    if conflicts.is_empty() {
+        return None;
+    }
+
+    let distr = rand_distr::Normal::new(flow.critical_gap_mean, flow.critical_gap_stdv).unwrap();
+    let critical_gap = rand::Rng::sample(rng, distr).max(0.0);
+
+    Some(GapAcceptance {
+        critical_gap,
+        hold_distance,
+        axis,
+        sign,
+        conflicts,
+        accepted: false,
+    })
+}
+
+pub fn crosswalk_crossings(
+    settings: &settings::Settings,
+    flow: &settings::VehFlow,
+    trajectory_series: &[[f64; 2]],
+    rng: &mut impl rand::Rng,
+) -> Vec<CrosswalkCrossing> {
+    let m = Rotation2::new(settings.angle.to_radians());
+
+    let sa = settings.cw_setback_along;
+    let wa = settings.cw_width_along;
+    let sc = settings.cw_setback_across;
+    let wc = settings.cw_width_across;
+    let arms = [
+        (
+            Rotation2::identity(),
+            [-sa - wa, -sa],
+            settings.width_along * 0.5,
+        ),
+        (
+            Rotation2::identity(),
+            [sa, sa + wa],
+            settings.width_along * 0.5,
+        ),
+        (m, [-sc - wc, -sc], settings.width_across * 0.5),
+        (m, [sc, sc + wc], settings.width_across * 0.5),
+    ];
+
+    let distr = rand_distr::Uniform::new(0.0, 1.0);
+    let yields = rand::Rng::sample(rng, distr) < flow.yield_prob;
+
+    let arc_lengths = arc_lengths(trajectory_series);
+
+    // first point of the path entering each crosswalk
+    let mut crossings = vec![];
+    for (axis, band, half_width) in arms {
+        let point = trajectory_series.iter().position(|p| {
+            let p = axis.inverse() * Point2::from(*p);
+            band[0] <= p.x && p.x <= band[1] && p.y.abs() <= half_width
+        });
+        let Some(j) = point.filter(|v| *v > 0) else {
+            continue;
+        };
+        let p = axis.inverse() * Point2::from(trajectory_series[j]);
+        crossings.push(CrosswalkCrossing {
+            hold_distance: arc_lengths[j],
+            axis,
+            band,
+            lateral: p.y,
+            yields,
+            decided: false,
+            occupied: false,
+        });
+    }
+
+    crossings
+}
+
+pub fn update_crosswalks(agents: &mut [forward::Agent], secs: f64, events: &mut Vec<YieldEvent>) {
+    let peds: Vec<_> = agents
+        .iter()
+        .filter(|v| matches!(v.output, forward::AgentOutput::Ped(_)))
+        .map(|v| (v.id, v.kind, v.flow_index, Point2::from(v.position())))
+        .collect();
+
+    for agent in agents.iter_mut() {
+        let position = agent.position();
+        let Some(motion) = agent.motion.as_mut().filter(|v| !v.protected) else {
+            continue;
+        };
+
+        for crossing in &mut motion.crosswalks {
+            let s = crossing.hold_distance - motion.distance;
+            if s <= 0.0 {
+                crossing.occupied = false;
+                continue;
+            }
+
+            // pedestrian on the crosswalk near the vehicle path
+            let occupant = peds.iter().find(|(_, _, _, p)| {
+                let p = crossing.axis.inverse() * p;
+                crossing.band[0] - PED_MARGIN <= p.x
+                    && p.x <= crossing.band[1] + PED_MARGIN
+                    && (p.y - crossing.lateral).abs() < YIELD_LATERAL
+            });
+            crossing.occupied = occupant.is_some();
+
+            let Some(&(ped_id, ped_kind, ped_flow_index, _)) = occupant else {
+                continue;
+            };
+            if crossing.decided || s > YIELD_DISTANCE {
+                continue;
+            }
+            crossing.decided = true;
+
+            events.push(YieldEvent {
+                veh_id: agent.id,
+                veh_kind: agent.kind,
+                veh_flow_index: agent.flow_index,
+                ped_id,
+                ped_kind,
+                ped_flow_index,
+                secs,
+                point: position,
+                distance: s,
+                speed: motion.speed,
+                yielded: crossing.yields,
+            });
+        }
+    }
+}
+
+// smallest time gap before an opposing through vehicle reaches the conflict points
+pub fn opposing_gap(agent: &forward::Agent, agents: &[forward::Agent]) -> Option<f64> {
+    let motion = agent.motion.as_ref()?;
+    let gap = motion.gap.as_ref()?;
+
+    let mut min_gap = f64::INFINITY;
+    for other in agents {
+        if other.kind != forward::AgentKind::StVeh {
+            continue;
+        }
+        let Some(&(_, x_c)) = gap.conflicts.iter().find(|v| v.0 == other.flow_index) else {
+            continue;
+        };
+        let Some(other_motion) = &other.motion else {
+            continue;
+        };
+
+        let x_o = (gap.axis.inverse() * Point2::from(other.position())).x;
+        let d = (x_c - x_o) * gap.sign;
+        if d < -CONFLICT_MARGIN {
+            continue;
+        }
+        if d <= CONFLICT_MARGIN {
+            return Some(0.0);
+        }
+        min_gap = min_gap.min(d / other_motion.speed.max(0.1));
+    }
+
+    Some(min_gap)
+}
+
+pub fn update_gap_acceptance(agents: &mut [forward::Agent]) {
+    let mut accepted = vec![];
+    for (i, agent) in agents.iter().enumerate() {
+        let Some(motion) = &agent.motion else {
+            continue;
+        };
+        let Some(gap) = motion
+            .gap
+            .as_ref()
+            .filter(|v| !v.accepted && !motion.protected)
+        else {
+            continue;
+        };
+        if gap.hold_distance - motion.distance > DECISION_DISTANCE {
+            continue;
+        }
+        if opposing_gap(agent, agents).is_some_and(|v| v >= gap.critical_gap) {
+            accepted.push(i);
+        }
+    }
+
+    for i in accepted {
+        if let Some(gap) = agents[i].motion.as_mut().and_then(|v| v.gap.as_mut()) {
+            gap.accepted = true;
+        }
+    }
+}
+
+pub fn update_leaders(agents: &mut [forward::Agent]) {
+    let mut leaders = vec![None; agents.len()];
+    for (i, agent) in agents.iter().enumerate() {
+        let Some(motion) = &agent.motion else {
+            continue;
+        };
+        let p = Point2::from(agent.position());
+
+        for other in agents.iter() {
+            let Some(other_motion) = &other.motion else {
+                continue;
+            };
+            if other.id == agent.id {
+                continue;
+            }
+
+            // rear of the vehicle ahead in this vehicle's path distance
+            let rear = if other.kind == agent.kind && other.flow_index == agent.flow_index {
+                if other_motion.distance <= motion.distance {
+                    continue;
+                }
+                other_motion.distance - VEH_LENGTH
+            } else {
+                let (Some(a), Some(b)) = (&motion.approach, &other_motion.approach) else {
+                    continue;
+                };
+                if a.lane != b.lane {
+                    continue;
+                }
+                let p = a.axis.inverse() * p;
+                let q = a.axis.inverse() * Point2::from(other.position());
+                if (p.y - a.lateral).abs() > LANE_TOLERANCE
+                    || (q.y - a.lateral).abs() > LANE_TOLERANCE
+                {
+                    continue;
+                }
+                let dx = a.sign * (q.x - p.x);
+                if dx <= 0.0 {
+                    continue;
+                }
+                motion.distance + dx - VEH_LENGTH
+            };
+
+            if leaders[i].is_some_and(|(v, _)| v <= rear) {
+                continue;
+            }
+            leaders[i] = Some((rear, other_motion.speed));
+        }
+    }
+
+    for (agent, leader) in agents.iter_mut().zip(leaders) {
+        if let Some(motion) = &mut agent.motion {
+            motion.leader = leader;
+        }
+    }
+}
+
+// lane of an inbound approach and the point its paths start from
+pub fn entry(
+    settings: &settings::Settings,
+    flow: &settings::VehFlow,
+) -> Option<((settings::Dir, usize), [f64; 2])> {
+    let (axis, sign, lanes, _) = approach_axis(settings, flow.src_dir)?;
+    let p = axis * Point2::new(-sign * compute::ROAD_LENGTH, lanes[flow.src_lane]);
+    Some(((flow.src_dir, flow.src_lane), [p.x, p.y]))
+}
+
+// vehicle on the same approach lane too close to enter behind
+pub fn entry_blocked(
+    agents: &[forward::Agent],
+    lane: (settings::Dir, usize),
+    start: [f64; 2],
+) -> bool {
+    agents.iter().any(|other| {
+        let Some(other_approach) = other.motion.as_ref().and_then(|v| v.approach.as_ref()) else {
+            return false;
+        };
+        if other_approach.lane != lane {
+            return false;
+        }
+        let [x0, y0] = start;
+        let [x1, y1] = other.position();
+        f64::hypot(x1 - x0, y1 - y0) < VEH_LENGTH + MIN_GAP
+    })
+}
+
+fn arc_lengths(series: &[[f64; 2]]) -> Vec<f64> {
+    let mut arc_lengths = Vec::with_capacity(series.len());
+    let mut total = 0.0;
+    for (i, p) in series.iter().enumerate() {
+        if i > 0 {
+            let [x0, y0] = series[i - 1];
+            total += f64::hypot(p[0] - x0, p[1] - y0);
+        }
+        arc_lengths.push(total);
+    }
+    arc_lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // straight path in steps of 0.1 m along x
+    fn lane_path(x0: f64, x1: f64, y: f64) -> Vec<[f64; 2]> {
+        let n = ((x1 - x0).abs() / 0.1).round() as usize;
+        let dx = (x1 - x0) / n as f64;
+        (0..=n).map(|k| [x0 + dx * k as f64, y]).collect()
+    }
+
+    // at the given path distance and speed
+    fn place(agent: &mut forward::Agent, distance: f64, speed: f64) {
+        let motion = agent.motion.as_mut().unwrap();
+        motion.distance = distance;
+        motion.speed = speed;
+        agent.step = motion.step_at(0);
+    }
+
+    fn motion(speed: f64, stop_distance: Option<f64>) -> VehMotion {
+        VehMotion {
+            distance: 0.0,
+            speed,
+            arc_lengths: vec![],
+            approach: Some(Approach {
+                lane: (settings::Dir::NxPy, 0),
+                axis: Rotation2::identity(),
+                sign: 1.0,
+                lateral: 0.0,
+                stop_distance,
+            }),
+            gap: None,
+            crosswalks: vec![],
+            leader: None,
+            stop: false,
+            go: None,
+            protected: false,
+        }
+    }
+
+    #[test]
+    fn follower_stops_behind_stopped_leader() {
+        let mut motion = motion(15.0, None);
+        motion.leader = Some((40.0, 0.0));
+        for _ in 0..3000 {
+            motion.advance(15.0, compute::STEP);
+            assert!(motion.distance < 40.0);
+        }
+        assert!(motion.speed < 1e-3);
+        assert!(motion.distance > 40.0 - 2.0 * MIN_GAP);
+    }
+
+    #[test]
+    fn can_stop_at_comfortable_decel() {
+        // 10 m/s stops within 25 m at 2 m/s2
+        let mut motion = motion(10.0, Some(50.0));
+        motion.distance = 25.0 - 1e-6;
+        assert!(can_stop(&motion, COMFORT_DECEL));
+        motion.distance = 25.0 + 1e-6;
+        assert!(!can_stop(&motion, COMFORT_DECEL));
+
+        // past the stop line or without one
+        motion.distance = 51.0;
+        assert!(!can_stop(&motion, COMFORT_DECEL));
+        let motion = self::motion(0.0, None);
+        assert!(!can_stop(&motion, COMFORT_DECEL));
+    }
+
+    #[test]
+    fn leaders_on_the_same_lane_only() {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+        let lanes = forward.settings.lane_along.clone();
+
+        // the through and left-turn flows share the kerb lane, the right turn is next to it
+        let path = lane_path(-60.0, 0.0, lanes[3]);
+        let follower = forward.spawn_sampled(forward::AgentKind::LtVeh, 0, Some(path.clone()));
+        place(follower, 0.0, 10.0);
+        let leader = forward.spawn_sampled(forward::AgentKind::StVeh, 0, Some(path));
+        place(leader, 20.0, 4.0);
+        let path = lane_path(-60.0, 0.0, lanes[2]);
+        let beside = forward.spawn_sampled(forward::AgentKind::RtVeh, 0, Some(path));
+        place(beside, 10.0, 6.0);
+
+        update_leaders(&mut forward.agents);
+        let leaders: Vec<_> = forward
+            .agents
+            .iter()
+            .map(|v| v.motion.as_ref().unwrap().leader)
+            .collect();
+        assert_eq!(leaders[0], Some((20.0 - VEH_LENGTH, 4.0)));
+        assert_eq!(leaders[1], None);
+        assert_eq!(leaders[2], None);
+    }
+
+    #[test]
+    fn entry_blocked_within_spawn_gap() {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+        let flow = forward.settings.st_veh_flows[0].clone();
+        let (lane, [x, y]) = entry(&forward.settings, &flow).unwrap();
+        assert_eq!(lane, (settings::Dir::NxPy, 3));
+        assert_eq!([x, y], [-compute::ROAD_LENGTH, 6.25]);
+
+        let path = lane_path(x, 0.0, y);
+        let agent = forward.spawn_sampled(forward::AgentKind::LtVeh, 0, Some(path));
+        place(agent, VEH_LENGTH + MIN_GAP - 0.5, 10.0);
+        assert!(entry_blocked(&forward.agents, lane, [x, y]));
+        assert!(!entry_blocked(
+            &forward.agents,
+            (settings::Dir::NxPy, 2),
+            [x, y]
+        ));
+
+        place(&mut forward.agents[0], VEH_LENGTH + MIN_GAP + 0.5, 10.0);
+        assert!(!entry_blocked(&forward.agents, lane, [x, y]));
+    }
+
+    // a right turn at its decision point and an opposing through vehicle the given time away
+    fn opposing(secs: f64) -> forward::Forward {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+        let settings = forward.settings.clone();
+
+        let turn = forward.spawn_sampled(forward::AgentKind::RtVeh, 0, None);
+        let gap = turn.motion.as_mut().unwrap().gap.as_mut().unwrap();
+        gap.critical_gap = 4.5;
+        let (hold_distance, (flow_index, x_c)) = (gap.hold_distance, gap.conflicts[0]);
+        place(turn, hold_distance - DECISION_DISTANCE + 1.0, 5.0);
+
+        // the opposing flow travels towards -x
+        let path = lane_path(
+            x_c + 150.0,
+            x_c - 50.0,
+            settings.lane_along[settings.st_veh_flows[flow_index].src_lane],
+        );
+        let through = forward.spawn_sampled(forward::AgentKind::StVeh, flow_index, Some(path));
+        place(through, 150.0 - 10.0 * secs, 10.0);
+        forward
+    }
+
+    #[test]
+    fn right_turn_waits_for_a_gap() {
+        let mut forward = opposing(2.0);
+        let gap = opposing_gap(&forward.agents[0], &forward.agents).unwrap();
+        assert!((gap - 2.0).abs() < 1e-9);
+        update_gap_acceptance(&mut forward.agents);
+
+        let motion = forward.agents[0].motion.as_ref().unwrap();
+        let gap = motion.gap.as_ref().unwrap();
+        assert!(!gap.accepted);
+        assert!(motion
+            .obstacles()
+            .contains(&(gap.hold_distance - motion.distance, 0.0)));
+    }
+
+    #[test]
+    fn right_turn_accepts_a_long_gap() {
+        let mut forward = opposing(6.0);
+        update_gap_acceptance(&mut forward.agents);
+
+        let motion = forward.agents[0].motion.as_ref().unwrap();
+        assert!(motion.gap.as_ref().unwrap().accepted);
+        assert!(motion.obstacles().is_empty());
+    }
+
+    #[test]
+    fn turn_stops_for_pedestrian_on_crosswalk() {
+        let mut forward = forward::Forward::new(Default::default()).unwrap();
+
+        let turn = forward.spawn_sampled(forward::AgentKind::LtVeh, 0, None);
+        let motion = turn.motion.as_mut().unwrap();
+        for crossing in &mut motion.crosswalks {
+            crossing.yields = true;
+        }
+        let crossing = motion.crosswalks[0].clone();
+        place(turn, crossing.hold_distance - 40.0, 8.0);
+
+        // standing mid-crosswalk on the vehicle path
+        let x = (crossing.band[0] + crossing.band[1]) * 0.5;
+        let p = crossing.axis * Point2::new(x, crossing.lateral);
+        forward.spawn_sampled(forward::AgentKind::Ped, 0, Some(vec![[p.x, p.y]; 2]));
+
+        let mut events = vec![];
+        for k in 0..2000 {
+            update_crosswalks(&mut forward.agents, k as f64 * compute::STEP, &mut events);
+            let agent = &mut forward.agents[0];
+            let (Some(motion), forward::AgentOutput::Veh(output)) =
+                (&mut agent.motion, &agent.output)
+            else {
+                unreachable!();
+            };
+            motion.advance(output.velocity_series[agent.step][1], compute::STEP);
+            agent.step = motion.step_at(agent.step);
+            assert!(motion.distance < crossing.hold_distance);
+        }
+
+        let motion = forward.agents[0].motion.as_ref().unwrap();
+        assert!(motion.speed < 1e-3);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].veh_id, events[0].ped_id), (0, 1));
+        assert!(events[0].yielded);
+        assert!(events[0].distance <= YIELD_DISTANCE);
+    }
+}
@@ -1,12 +1,127 @@
+mod actuated;
+mod calibrate;
+mod colmap;
+mod compute;
+mod conflict;
+mod export;
 mod forward;
+mod gps;
+mod headless;
+mod interaction;
+mod model;
 mod plot;
+mod report;
 mod settings;
-mod compute;
+mod signal_plan;
+mod validator;
 mod widget;
 
 use egui_miniquad as egui_mq;
 use miniquad as mq;
 
+#[derive(clap::Parser)]
+#[command(name = "safety-traffic-simulation")]
+struct Cli {
+    /// Scenario file (.toml or .json)
+    #[arg(long, global = true)]
+    scenario: Option<std::path::PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run the simulation without a window at a fixed timestep
+    Run {
+        /// Simulated duration [sec]
+        #[arg(short, long, default_value_t = 3600.0)]
+        duration: f64,
+        /// Simulated timestep [sec]
+        #[arg(short, long, default_value_t = 0.1)]
+        step: f64,
+        /// Output directory
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+        /// Also write SUMO floating car data (fcd.xml)
+        #[arg(long)]
+        fcd: bool,
+    },
+    /// Fit turning vehicle coefficients of the scenario's behaviour model to observed features
+    ///
+    /// Base coefficients that are exactly 0.0 stay fixed at zero and are not fitted.
+    Calibrate {
+        /// Observed per-vehicle features (.csv), without a kind column every row is lt_veh
+        #[arg(short, long)]
+        input: std::path::PathBuf,
+        /// Output behaviour model (.toml)
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+        /// Version recorded in the output model
+        #[arg(long)]
+        model_version: String,
+        /// Region or study site of the observations
+        #[arg(long)]
+        site: Option<String>,
+    },
+    /// Import observed runs from gps files (dt,x,y) into the local frame of the scenario
+    ImportGps {
+        /// GPS files, one run per file
+        #[arg(short, long, num_args = 1.., required = true)]
+        input: Vec<std::path::PathBuf>,
+        /// Movement of the runs (st_veh, lt_veh or rt_veh)
+        #[arg(short, long, default_value = "lt_veh")]
+        kind: String,
+        /// Files start with a header row
+        #[arg(long)]
+        header: bool,
+        /// Shift timestamps from UTC to local time (+9h)
+        #[arg(long)]
+        utc: bool,
+        /// Observed vehicles are large vehicles
+        #[arg(long)]
+        large: bool,
+        /// Output directory
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+    },
+    /// Import camera tracks of colmap models (images.bin or images.txt) as observed runs
+    ImportColmap {
+        /// Model directories, aligned by an anchors.csv (frame,x,y) inside each
+        #[arg(short, long, num_args = 1.., required = true)]
+        input: Vec<std::path::PathBuf>,
+        /// Movement of the runs (st_veh, lt_veh or rt_veh)
+        #[arg(short, long, default_value = "lt_veh")]
+        kind: String,
+        /// Meters per model unit for models without anchors
+        #[arg(long, default_value_t = 1.0)]
+        scale: f64,
+        /// Frame rate of the extracted images
+        #[arg(long, default_value_t = colmap::FPS)]
+        fps: f64,
+        /// Observed vehicles are large vehicles
+        #[arg(long)]
+        large: bool,
+        /// Output directory
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+    },
+    /// Compare simulated vehicle profiles of the scenario with observations
+    Report {
+        /// Observed per-vehicle values with kind, v_min, x_min and t_exit columns (.csv)
+        #[arg(long)]
+        observed: std::path::PathBuf,
+        /// Observed profiles with id, kind, t, velocity, x and curvature columns (.csv)
+        #[arg(long)]
+        profiles: Option<std::path::PathBuf>,
+        /// Simulated vehicles per flow
+        #[arg(short, long, default_value_t = 1000)]
+        samples: usize,
+        /// Output directory
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+    },
+}
+
 struct State {
     egui_mq: egui_miniquad::EguiMq,
     mq_ctx: Box<dyn mq::RenderingBackend>,
@@ -15,9 +130,9 @@ struct State {
 }
 
 impl State {
-    fn new() -> Self {
+    fn new(widget: widget::Widget) -> Self {
         let mut mq_ctx = mq::window::new_rendering_backend();
-        let mut widget = widget::Widget::new();
+        let mut widget = widget;
         Self {
             egui_mq: egui_mq::EguiMq::new(&mut *mq_ctx),
             mq_ctx,
@@ -95,6 +210,87 @@ fn main() {
         .apply()
         .unwrap();
 
+    let cli = <Cli as clap::Parser>::parse();
+
+    let settings = match &cli.scenario {
+        Some(path) => match settings::Settings::load(path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::error!("failed to load {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => Default::default(),
+    };
+
+    if let Some(command) = cli.command {
+        match command {
+            Command::Run {
+                duration,
+                step,
+                output,
+                fcd,
+            } => {
+                if let Err(err) = headless::run(settings, duration, step, &output, fcd) {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+            Command::Calibrate {
+                input,
+                output,
+                model_version,
+                site,
+            } => {
+                let site = site.as_deref();
+                if let Err(err) = calibrate::run(&settings, &input, &output, &model_version, site) {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+            Command::ImportGps {
+                input,
+                kind,
+                header,
+                utc,
+                large,
+                output,
+            } => {
+                if let Err(err) = gps::run(&settings, &input, &kind, header, utc, large, &output) {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+            Command::ImportColmap {
+                input,
+                kind,
+                scale,
+                fps,
+                large,
+                output,
+            } => {
+                if let Err(err) = colmap::run(&settings, &input, &kind, scale, fps, large, &output)
+                {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+            Command::Report {
+                observed,
+                profiles,
+                samples,
+                output,
+            } => {
+                let profiles = profiles.as_deref();
+                if let Err(err) = report::run(&settings, &observed, profiles, samples, &output) {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
     let conf = mq::conf::Conf {
         window_title: "safety-traffic-simulation".into(),
         window_width: 1280,
@@ -104,5 +300,11 @@ fn main() {
         ..Default::default()
     };
 
-    mq::start(conf, || Box::new(State::new()));
+    let scenario_path = match &cli.scenario {
+        Some(path) => path.display().to_string(),
+        None => "scenario.toml".into(),
+    };
+    let widget = widget::Widget::new(settings, scenario_path);
+
+    mq::start(conf, || Box::new(State::new(widget)));
 }
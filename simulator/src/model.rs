@@ -0,0 +1,286 @@
+use crate::settings;
+
+pub const DEFAULT_MODEL: &str = include_str!("../models/default.toml");
+
+// regression coefficients, in the regressor order of compute.rs
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Coef {
+    pub coef: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub std_err: Vec<f64>,
+}
+
+impl Coef {
+    pub fn dot(&self, x: &[f64]) -> f64 {
+        let a = nalgebra::DVectorView::from_slice(&self.coef, self.coef.len());
+        let x = nalgebra::DVectorView::from_slice(x, x.len());
+        a.dot(&x)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Gamma {
+    pub shape: Coef,
+    pub scale: Coef,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ShiftedGamma {
+    pub shape: Coef,
+    pub scale: Coef,
+    pub shift: Coef,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Normal {
+    pub mean: Coef,
+    pub std_dev: Coef,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Weibull {
+    pub shape: Coef,
+    pub scale: Coef,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Clothoid {
+    pub a1: Coef,
+    pub a2: Coef,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct LtVehModel {
+    pub c_in: Gamma,
+    pub c_out: Gamma,
+    pub v_min: Normal,
+    pub x_min: Normal,
+    pub r_min: Normal,
+    pub clothoid: Clothoid,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct RtVehModel {
+    pub c_in: Gamma,
+    pub c_out: Gamma,
+    pub v_min: Normal,
+    pub x_min: Normal,
+    pub r_min: Weibull,
+    pub clothoid: Clothoid,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct PedModel {
+    pub v_1: Gamma,
+    pub v_2: Gamma,
+    pub x_1: Weibull,
+    pub x_2: Weibull,
+    pub x_3: Weibull,
+}
+
+// clearance speed a countdown at entry raises both half velocities to
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Countdown {
+    pub v_clear: Coef,
+    pub max_speed: f64,
+}
+
+impl Default for Countdown {
+    fn default() -> Self {
+        Self {
+            v_clear: Coef {
+                coef: vec![1.0, 0.0],
+                ..Default::default()
+            },
+            max_speed: 2.5,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct IgPedModel {
+    pub v_0: Gamma,
+    pub v_1: ShiftedGamma,
+    pub v_2: ShiftedGamma,
+    pub x_1: Weibull,
+    pub x_2: Weibull,
+    pub x_3: Weibull,
+    // models saved before the countdown input keep the plain clearance speed
+    #[serde(default)]
+    pub countdown: Countdown,
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct BehaviourModel {
+    pub name: String,
+    pub version: String,
+    pub site: String,
+    pub lt_veh: LtVehModel,
+    pub rt_veh: RtVehModel,
+    pub ped: PedModel,
+    pub ig_ped: IgPedModel,
+}
+
+impl Default for BehaviourModel {
+    fn default() -> Self {
+        toml::from_str(DEFAULT_MODEL).unwrap()
+    }
+}
+
+impl BehaviourModel {
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let model: Self = toml::from_str(&text).map_err(invalid_data)?;
+        model.validate().map_err(invalid_data)?;
+        Ok(model)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let text = toml::to_string_pretty(self).map_err(invalid_data)?;
+        std::fs::write(path, text)
+    }
+
+    // the model a scenario refers to, embedded default without a path
+    pub fn from_settings(settings: &settings::Settings) -> std::io::Result<Self> {
+        match settings.model_path.is_empty() {
+            true => Ok(Default::default()),
+            false => Self::load(std::path::Path::new(&settings.model_path)),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{} {} ({})", self.name, self.version, self.site)
+    }
+
+    // every coefficient vector with its number of regressors
+    pub fn terms(&self) -> Vec<(&'static str, &Coef, usize)> {
+        let lt = &self.lt_veh;
+        let rt = &self.rt_veh;
+        let ped = &self.ped;
+        let ig = &self.ig_ped;
+        vec![
+            ("lt_veh.c_in.shape", &lt.c_in.shape, 6),
+            ("lt_veh.c_in.scale", &lt.c_in.scale, 5),
+            ("lt_veh.c_out.shape", &lt.c_out.shape, 6),
+            ("lt_veh.c_out.scale", &lt.c_out.scale, 5),
+            ("lt_veh.v_min.mean", &lt.v_min.mean, 6),
+            ("lt_veh.v_min.std_dev", &lt.v_min.std_dev, 3),
+            ("lt_veh.x_min.mean", &lt.x_min.mean, 6),
+            ("lt_veh.x_min.std_dev", &lt.x_min.std_dev, 3),
+            ("lt_veh.r_min.mean", &lt.r_min.mean, 4),
+            ("lt_veh.r_min.std_dev", &lt.r_min.std_dev, 4),
+            ("lt_veh.clothoid.a1", &lt.clothoid.a1, 6),
+            ("lt_veh.clothoid.a2", &lt.clothoid.a2, 6),
+            ("rt_veh.c_in.shape", &rt.c_in.shape, 2),
+            ("rt_veh.c_in.scale", &rt.c_in.scale, 2),
+            ("rt_veh.c_out.shape", &rt.c_out.shape, 2),
+            ("rt_veh.c_out.scale", &rt.c_out.scale, 2),
+            ("rt_veh.v_min.mean", &rt.v_min.mean, 3),
+            ("rt_veh.v_min.std_dev", &rt.v_min.std_dev, 3),
+            ("rt_veh.x_min.mean", &rt.x_min.mean, 3),
+            ("rt_veh.x_min.std_dev", &rt.x_min.std_dev, 3),
+            ("rt_veh.r_min.shape", &rt.r_min.shape, 2),
+            ("rt_veh.r_min.scale", &rt.r_min.scale, 2),
+            ("rt_veh.clothoid.a1", &rt.clothoid.a1, 5),
+            ("rt_veh.clothoid.a2", &rt.clothoid.a2, 5),
+            ("ped.v_1.shape", &ped.v_1.shape, 5),
+            ("ped.v_1.scale", &ped.v_1.scale, 6),
+            ("ped.v_2.shape", &ped.v_2.shape, 5),
+            ("ped.v_2.scale", &ped.v_2.scale, 6),
+            ("ped.x_1.shape", &ped.x_1.shape, 9),
+            ("ped.x_1.scale", &ped.x_1.scale, 6),
+            ("ped.x_2.shape", &ped.x_2.shape, 9),
+            ("ped.x_2.scale", &ped.x_2.scale, 6),
+            ("ped.x_3.shape", &ped.x_3.shape, 9),
+            ("ped.x_3.scale", &ped.x_3.scale, 6),
+            ("ig_ped.v_0.shape", &ig.v_0.shape, 2),
+            ("ig_ped.v_0.scale", &ig.v_0.scale, 2),
+            ("ig_ped.v_1.shape", &ig.v_1.shape, 3),
+            ("ig_ped.v_1.scale", &ig.v_1.scale, 3),
+            ("ig_ped.v_1.shift", &ig.v_1.shift, 2),
+            ("ig_ped.v_2.shape", &ig.v_2.shape, 2),
+            ("ig_ped.v_2.scale", &ig.v_2.scale, 2),
+            ("ig_ped.v_2.shift", &ig.v_2.shift, 3),
+            ("ig_ped.x_1.shape", &ig.x_1.shape, 9),
+            ("ig_ped.x_1.scale", &ig.x_1.scale, 6),
+            ("ig_ped.x_2.shape", &ig.x_2.shape, 9),
+            ("ig_ped.x_2.scale", &ig.x_2.scale, 6),
+            ("ig_ped.x_3.shape", &ig.x_3.shape, 9),
+            ("ig_ped.x_3.scale", &ig.x_3.scale, 6),
+            ("ig_ped.countdown.v_clear", &ig.countdown.v_clear, 2),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, coef, len) in self.terms() {
+            if coef.coef.len() != len {
+                let n = coef.coef.len();
+                return Err(format!("{name} has {n} coefficients, expected {len}"));
+            }
+            if !coef.std_err.is_empty() && coef.std_err.len() != len {
+                let n = coef.std_err.len();
+                return Err(format!("{name} has {n} standard errors, expected {len}"));
+            }
+        }
+        let max_speed = self.ig_ped.countdown.max_speed;
+        if max_speed.is_nan() || max_speed <= 0.0 {
+            return Err(format!(
+                "ig_ped.countdown.max_speed is {max_speed}, expected positive"
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(err: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut model = BehaviourModel {
+            version: "round-trip".to_string(),
+            ..Default::default()
+        };
+        model.lt_veh.v_min.mean.std_err = vec![0.1; 6];
+
+        let path = std::env::temp_dir().join(format!("model-{}.toml", std::process::id()));
+        model.save(&path).unwrap();
+        let loaded = BehaviourModel::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), model);
+    }
+
+    #[test]
+    fn load_rejects_wrong_length() {
+        let mut model = BehaviourModel::default();
+        model.rt_veh.c_in.shape.coef.push(0.0);
+        assert!(model.validate().is_err());
+    }
+
+    #[test]
+    fn load_without_countdown() {
+        let text = DEFAULT_MODEL.split("[ig_ped.countdown]").next().unwrap();
+        let model: BehaviourModel = toml::from_str(text).unwrap();
+        assert_eq!(model.ig_ped.countdown, Countdown::default());
+        assert_eq!(model, BehaviourModel::default());
+    }
+
+    #[test]
+    fn dot_any_length() {
+        let coef = Coef {
+            coef: vec![1.0, 2.0, 3.0, 4.0],
+            ..Default::default()
+        };
+        assert_eq!(coef.dot(&[1.0, 1.0, 1.0, 1.0]), 10.0);
+        let coef = Coef {
+            coef: vec![1.0, -2.0],
+            ..Default::default()
+        };
+        assert_eq!(coef.dot(&[3.0, 1.0]), 1.0);
+    }
+}
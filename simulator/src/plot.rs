@@ -229,10 +229,28 @@ impl settings::Settings {
             let line = egui_plot::Line::new(vec![p0, p1]).color(egui::Color32::GREEN);
             lines.push(line);
 
+            // protected arrow
+            if let Some([a0, a1]) = signal.arrow_window(signal.green_secs) {
+                let p0 = nalgebra::Point2::new(s0 + a0, layer).into();
+                let p1 = nalgebra::Point2::new(s0 + a1, layer).into();
+                let line = egui_plot::Line::new(vec![p0, p1])
+                    .color(egui::Color32::LIGHT_GREEN)
+                    .width(3.0);
+                lines.push(line);
+            }
+
             let p0 = nalgebra::Point2::new(s1, layer).into();
             let p1 = nalgebra::Point2::new(s2, layer).into();
             let line = egui_plot::Line::new(vec![p0, p1]).color(egui::Color32::YELLOW);
             lines.push(line);
+
+            lines.extend(red_lines(
+                layer,
+                s2,
+                signal.cycle_secs,
+                signal.all_red_secs,
+                signal.red_secs,
+            ));
         }
 
         for (i, signal) in self.ped_signals.iter().enumerate() {
@@ -259,6 +277,14 @@ impl settings::Settings {
                 .color(egui::Color32::GREEN)
                 .style(egui_plot::LineStyle::dotted_dense());
             lines.push(line);
+
+            lines.extend(red_lines(
+                layer,
+                s2,
+                signal.cycle_secs,
+                signal.all_red_secs,
+                signal.red_secs,
+            ));
         }
 
         egui_plot::Plot::new("TLS schedule")
@@ -271,7 +297,62 @@ impl settings::Settings {
                 lines.into_iter().for_each(|v| plot_ui.line(v));
                 overlay_fn(plot_ui);
             });
+
+        // signal validation, rerun only when the settings change
+        let id = egui::Id::new("signal validation");
+        let violations = ui.data_mut(|data| {
+            let cache: &mut Option<(settings::Settings, Vec<validator::Violation>)> =
+                data.get_temp_mut_or_default(id);
+            if cache.as_ref().is_none_or(|(settings, _)| settings != self) {
+                *cache = Some((self.clone(), validator::validate_signals(self)));
+            }
+            cache.as_ref().unwrap().1.clone()
+        });
+        if violations.is_empty() {
+            ui.label(egui::RichText::new("No signal violations").color(egui::Color32::GREEN));
+        }
+        egui::ScrollArea::vertical()
+            .max_height(100.0)
+            .show(ui, |ui| {
+                for violation in &violations {
+                    ui.label(egui::RichText::new(violation.to_string()).color(egui::Color32::RED));
+                }
+            });
+    }
+}
+
+// all-red clearance then the remaining red, wrapped into the cycle
+fn red_lines(
+    layer: f64,
+    start: f64,
+    cycle_secs: f64,
+    all_red_secs: f64,
+    red_secs: f64,
+) -> Vec<egui_plot::Line> {
+    let s0 = start;
+    let s1 = start + f64::min(all_red_secs, red_secs);
+    let s2 = start + red_secs;
+
+    let mut lines = vec![];
+    for (s0, s1, all_red) in [(s0, s1, true), (s1, s2, false)] {
+        let mut s0 = s0;
+        while s0 < s1 && cycle_secs > 0.0 {
+            let base = (s0 / cycle_secs).floor() * cycle_secs;
+            let end = f64::min(s1, base + cycle_secs);
+
+            let p0 = nalgebra::Point2::new(s0 - base, layer).into();
+            let p1 = nalgebra::Point2::new(end - base, layer).into();
+            let line = egui_plot::Line::new(vec![p0, p1]);
+            let line = match all_red {
+                true => line.color(egui::Color32::DARK_RED).width(3.0),
+                false => line.color(egui::Color32::RED),
+            };
+            lines.push(line);
+
+            s0 = end;
+        }
     }
+    lines
 }
 
 fn radius_border(
@@ -0,0 +1,440 @@
+use std::io::Write;
+
+use crate::{compute, gps, model, settings};
+
+pub const QUANTILES: usize = 99;
+pub const TIME_BIN: f64 = 0.5;
+pub const DISTANCE_BIN: f64 = 1.0;
+pub const VARIABLES: [&str; 3] = ["v_min", "x_min", "t_exit"];
+pub const KINDS: [&str; 3] = ["st_veh", "lt_veh", "rt_veh"];
+
+// header and rows of a comma separated file
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn read(path: &std::path::Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut lines = text.lines().filter(|v| !v.trim().is_empty());
+        let split = |line: &str| line.split(',').map(|v| v.trim().to_string()).collect();
+        let header = lines.next().map(split).unwrap_or_default();
+        let rows = lines.map(split).collect();
+        Ok(Self { header, rows })
+    }
+
+    pub fn require(&self, names: &[&str]) -> std::io::Result<()> {
+        for name in names {
+            if !self.header.iter().any(|v| v == name) {
+                let msg = format!("missing column {name}");
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get<'a>(&self, row: &'a [String], name: &str) -> Option<&'a str> {
+        let j = self.header.iter().position(|v| v == name)?;
+        row.get(j).map(|v| v.as_str())
+    }
+
+    pub fn value(&self, row: &[String], name: &str) -> Option<f64> {
+        self.get(row, name)?
+            .parse()
+            .ok()
+            .filter(|v: &f64| v.is_finite())
+    }
+}
+
+// per kind values of v_min, x_min and t_exit with velocity and curvature profiles
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Sample {
+    pub values: ahash::AHashMap<(String, &'static str), Vec<f64>>,
+    pub velocity: ahash::AHashMap<String, Vec<Vec<[f64; 2]>>>,
+    pub curvature: ahash::AHashMap<String, Vec<Vec<[f64; 2]>>>,
+}
+
+impl Sample {
+    pub fn simulate(
+        settings: &settings::Settings,
+        model: &model::BehaviourModel,
+        samples: usize,
+    ) -> Self {
+        let mut rng: rand::rngs::StdRng = rand::SeedableRng::seed_from_u64(settings.seed);
+        let mut sample = Self::default();
+        for kind in KINDS {
+            let flows = match kind {
+                "st_veh" => &settings.st_veh_flows,
+                "lt_veh" => &settings.lt_veh_flows,
+                _ => &settings.rt_veh_flows,
+            };
+            for flow in flows {
+                for _ in 0..samples {
+                    let output = match kind {
+                        "st_veh" => compute::compute_st_veh(settings, flow, &mut rng),
+                        "lt_veh" => {
+                            compute::compute_lt_veh(settings, &model.lt_veh, flow, &mut rng)
+                        }
+                        _ => compute::compute_rt_veh(settings, &model.rt_veh, flow, &mut rng),
+                    };
+                    let Some(output) = output else {
+                        continue;
+                    };
+                    // t_exit over the section the observed runs are trimmed to
+                    let t_exit =
+                        gps::section_secs(&output.velocity_series, &output.position_series);
+                    let values = [output.v_min, output.x_min, t_exit];
+                    for (variable, value) in VARIABLES.into_iter().zip(values) {
+                        sample.push(kind, variable, value);
+                    }
+                    let velocity = sample.velocity.entry(kind.to_string()).or_default();
+                    velocity.push(output.velocity_series);
+                    let curvature = sample.curvature.entry(kind.to_string()).or_default();
+                    curvature.push(output.curvature_series);
+                }
+            }
+        }
+        sample
+    }
+
+    pub fn observe(
+        observed: &std::path::Path,
+        profiles: Option<&std::path::Path>,
+    ) -> std::io::Result<Self> {
+        let mut sample = Self::default();
+
+        let table = Table::read(observed)?;
+        table.require(&["kind"])?;
+        for row in &table.rows {
+            let Some(kind) = table.get(row, "kind") else {
+                continue;
+            };
+            for variable in VARIABLES {
+                if let Some(value) = table.value(row, variable) {
+                    sample.push(kind, variable, value);
+                }
+            }
+        }
+
+        let Some(profiles) = profiles else {
+            return Ok(sample);
+        };
+        let table = Table::read(profiles)?;
+        table.require(&["id", "kind"])?;
+        let mut velocity: ahash::AHashMap<(String, String), Vec<[f64; 2]>> = Default::default();
+        let mut curvature: ahash::AHashMap<(String, String), Vec<[f64; 2]>> = Default::default();
+        for row in &table.rows {
+            let (Some(id), Some(kind)) = (table.get(row, "id"), table.get(row, "kind")) else {
+                continue;
+            };
+            let key = (kind.to_string(), id.to_string());
+            if let (Some(t), Some(v)) = (table.value(row, "t"), table.value(row, "velocity")) {
+                velocity.entry(key.clone()).or_default().push([t, v]);
+            }
+            if let (Some(x), Some(c)) = (table.value(row, "x"), table.value(row, "curvature")) {
+                curvature.entry(key).or_default().push([x, c]);
+            }
+        }
+        for ((kind, _), mut series) in velocity {
+            series.sort_by(|a, b| a[0].total_cmp(&b[0]));
+            sample.velocity.entry(kind).or_default().push(series);
+        }
+        for ((kind, _), mut series) in curvature {
+            series.sort_by(|a, b| a[0].total_cmp(&b[0]));
+            sample.curvature.entry(kind).or_default().push(series);
+        }
+        Ok(sample)
+    }
+
+    fn push(&mut self, kind: &str, variable: &'static str, value: f64) {
+        if value.is_finite() {
+            let key = (kind.to_string(), variable);
+            self.values.entry(key).or_default().push(value);
+        }
+    }
+}
+
+// linear interpolation between order statistics
+pub fn quantile(sorted: &[f64], p: f64) -> f64 {
+    let h = (sorted.len() - 1) as f64 * p;
+    let i = h.floor() as usize;
+    let j = usize::min(i + 1, sorted.len() - 1);
+    sorted[i] + (h - i as f64) * (sorted[j] - sorted[i])
+}
+
+// two-sample kolmogorov-smirnov statistic on sorted samples
+pub fn ks_statistic(a: &[f64], b: &[f64]) -> f64 {
+    let (mut i, mut j) = (0, 0);
+    let mut d: f64 = 0.0;
+    while i < a.len() && j < b.len() {
+        let x = f64::min(a[i], b[j]);
+        while i < a.len() && a[i] <= x {
+            i += 1;
+        }
+        while j < b.len() && b[j] <= x {
+            j += 1;
+        }
+        let fa = i as f64 / a.len() as f64;
+        let fb = j as f64 / b.len() as f64;
+        d = d.max((fa - fb).abs());
+    }
+    d
+}
+
+// asymptotic p-value of the kolmogorov distribution
+pub fn ks_p_value(d: f64, n: usize, m: usize) -> f64 {
+    let en = (n as f64 * m as f64 / (n + m) as f64).sqrt();
+    let lambda = (en + 0.12 + 0.11 / en) * d;
+    if lambda < 1e-3 {
+        return 1.0;
+    }
+    let sum: f64 = (1..=100)
+        .map(|j| {
+            let sign = if j % 2 == 1 { 1.0 } else { -1.0 };
+            sign * (-2.0 * (j * j) as f64 * lambda * lambda).exp()
+        })
+        .sum();
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+fn interpolate(series: &[[f64; 2]], x: f64) -> Option<f64> {
+    let i = series.partition_point(|v| v[0] < x);
+    if i == 0 || i == series.len() {
+        return None;
+    }
+    let [x0, y0] = series[i - 1];
+    let [x1, y1] = series[i];
+    match x1 > x0 {
+        true => Some(y0 + (x - x0) / (x1 - x0) * (y1 - y0)),
+        false => Some(y0),
+    }
+}
+
+// values of every profile at a bin centre
+fn cross_section(profiles: &[Vec<[f64; 2]>], x: f64) -> Vec<f64> {
+    let mut values: Vec<_> = profiles.iter().filter_map(|v| interpolate(v, x)).collect();
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+fn range(profiles: &[Vec<[f64; 2]>]) -> Option<(f64, f64)> {
+    let min = profiles
+        .iter()
+        .filter_map(|v| v.first())
+        .map(|v| v[0])
+        .reduce(f64::min)?;
+    let max = profiles
+        .iter()
+        .filter_map(|v| v.last())
+        .map(|v| v[0])
+        .reduce(f64::max)?;
+    Some((min, max))
+}
+
+pub fn run(
+    settings: &settings::Settings,
+    observed: &std::path::Path,
+    profiles: Option<&std::path::Path>,
+    samples: usize,
+    output_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    let model = model::BehaviourModel::from_settings(settings)?;
+    let obs = Sample::observe(observed, profiles)?;
+    let sim = Sample::simulate(settings, &model, samples);
+    log::info!("simulated with model {}", model.label());
+
+    std::fs::create_dir_all(output_dir)?;
+
+    // distributions of the per-vehicle values
+    let file = std::fs::File::create(output_dir.join("ks.csv"))?;
+    let mut ks_writer = std::io::BufWriter::new(file);
+    writeln!(ks_writer, "kind,variable,n_sim,n_obs,statistic,p_value")?;
+    let file = std::fs::File::create(output_dir.join("qq.csv"))?;
+    let mut qq_writer = std::io::BufWriter::new(file);
+    writeln!(qq_writer, "kind,variable,probability,simulated,observed")?;
+    for kind in KINDS {
+        for variable in VARIABLES {
+            let key = (kind.to_string(), variable);
+            let (Some(a), Some(b)) = (sim.values.get(&key), obs.values.get(&key)) else {
+                continue;
+            };
+            let mut a = a.clone();
+            let mut b = b.clone();
+            a.sort_by(f64::total_cmp);
+            b.sort_by(f64::total_cmp);
+
+            let d = ks_statistic(&a, &b);
+            let p = ks_p_value(d, a.len(), b.len());
+            let (n, m) = (a.len(), b.len());
+            writeln!(ks_writer, "{kind},{variable},{n},{m},{d},{p}")?;
+            log::info!("{kind}.{variable}: n_sim {n}, n_obs {m}, KS {d:.3}, p {p:.3}");
+
+            for k in 1..=QUANTILES {
+                let prob = k as f64 / (QUANTILES + 1) as f64;
+                let (qa, qb) = (quantile(&a, prob), quantile(&b, prob));
+                writeln!(qq_writer, "{kind},{variable},{prob},{qa},{qb}")?;
+            }
+        }
+    }
+    ks_writer.flush()?;
+    qq_writer.flush()?;
+
+    // profile envelopes, with the share of observations inside the simulated 5-95%
+    let file = std::fs::File::create(output_dir.join("envelope.csv"))?;
+    let mut writer = std::io::BufWriter::new(file);
+    writeln!(
+        writer,
+        "kind,profile,x,sim_q05,sim_q50,sim_q95,obs_q05,obs_q50,obs_q95,n_obs,coverage"
+    )?;
+    for kind in KINDS {
+        let profiles = [
+            ("velocity", &sim.velocity, &obs.velocity, TIME_BIN),
+            ("curvature", &sim.curvature, &obs.curvature, DISTANCE_BIN),
+        ];
+        for (profile, sim_profiles, obs_profiles, bin) in profiles {
+            let (Some(a), Some(b)) = (sim_profiles.get(kind), obs_profiles.get(kind)) else {
+                continue;
+            };
+            let Some((min, max)) = range(b) else {
+                continue;
+            };
+
+            let mut inside = 0;
+            let mut total = 0;
+            let start = (min / bin).ceil() * bin;
+            for k in 0..=((max - start) / bin).floor() as usize {
+                let x = start + k as f64 * bin;
+                let sa = cross_section(a, x);
+                let sb = cross_section(b, x);
+                if sa.is_empty() || sb.is_empty() {
+                    continue;
+                }
+                let (lo, hi) = (quantile(&sa, 0.05), quantile(&sa, 0.95));
+                let n = sb.iter().filter(|v| lo <= **v && **v <= hi).count();
+                inside += n;
+                total += sb.len();
+                writeln!(
+                    writer,
+                    "{kind},{profile},{x},{lo},{},{hi},{},{},{},{},{}",
+                    quantile(&sa, 0.5),
+                    quantile(&sb, 0.05),
+                    quantile(&sb, 0.5),
+                    quantile(&sb, 0.95),
+                    sb.len(),
+                    n as f64 / sb.len() as f64,
+                )?;
+            }
+            if total > 0 {
+                let coverage = inside as f64 / total as f64;
+                log::info!("{kind}.{profile}: {coverage:.3} of observations in the 90% envelope");
+            }
+        }
+    }
+    writer.flush()?;
+
+    log::info!("wrote validation report into {}", output_dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantile_interpolates() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(quantile(&sorted, 0.0), 1.0);
+        assert_eq!(quantile(&sorted, 0.5), 2.5);
+        assert_eq!(quantile(&sorted, 1.0), 4.0);
+        assert_eq!(quantile(&[7.0], 0.9), 7.0);
+    }
+
+    #[test]
+    fn ks_statistic_of_shifted_samples() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(ks_statistic(&a, &a), 0.0);
+        assert_eq!(ks_statistic(&a, &[3.0, 4.0, 5.0, 6.0]), 0.5);
+        assert_eq!(ks_statistic(&a, &[5.0, 6.0]), 1.0);
+        assert_eq!(ks_statistic(&[5.0, 6.0], &a), 1.0);
+    }
+
+    #[test]
+    fn ks_p_value_bounds() {
+        assert_eq!(ks_p_value(0.0, 100, 100), 1.0);
+        assert!(ks_p_value(1.0, 100, 100) < 1e-6);
+        assert!(ks_p_value(0.1, 100, 100) > 0.5);
+    }
+
+    #[test]
+    fn ks_p_value_known_points() {
+        // effective size 10, so lambda is 10.131 d, Q(1) = 0.2700 and Q(1.3581) = 0.05
+        let en = 10.0 + 0.12 + 0.011;
+        assert!((ks_p_value(1.0 / en, 200, 200) - 0.2700).abs() < 1e-4);
+        assert!((ks_p_value(1.3581 / en, 200, 200) - 0.05).abs() < 1e-4);
+    }
+
+    #[test]
+    fn interpolate_within_series() {
+        let series = [[0.0, 0.0], [1.0, 10.0], [1.0, 20.0], [3.0, 0.0]];
+        assert_eq!(interpolate(&series, 0.5), Some(5.0));
+        assert_eq!(interpolate(&series, 2.0), Some(10.0));
+        assert_eq!(interpolate(&series, 1.0), Some(10.0));
+        // the first point has no left neighbour, past the last none either
+        assert_eq!(interpolate(&series, 0.0), None);
+        assert_eq!(interpolate(&series, 3.5), None);
+    }
+
+    #[test]
+    fn cross_section_of_covering_profiles() {
+        let profiles = vec![
+            vec![[0.0, 4.0], [2.0, 4.0]],
+            vec![[0.0, 0.0], [2.0, 2.0]],
+            vec![[1.5, 9.0], [3.0, 9.0]],
+        ];
+        assert_eq!(cross_section(&profiles, 1.0), [1.0, 4.0]);
+        assert_eq!(cross_section(&profiles, 1.75), [1.75, 4.0, 9.0]);
+        assert!(cross_section(&profiles, 5.0).is_empty());
+    }
+
+    #[test]
+    fn observe_values_and_profiles() {
+        let dir = std::env::temp_dir().join(format!("report-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let observed = dir.join("runs.csv");
+        let profiles = dir.join("profiles.csv");
+        std::fs::write(
+            &observed,
+            "kind,v_min,x_min,t_exit\nlt_veh,4.5,3,9\nlt_veh,,NaN,8\n\nrt_veh,3.5,2,7\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &profiles,
+            "id,kind,t,velocity,x,curvature\n0,lt_veh,1,5,2,0.1\n0,lt_veh,0,6,1,0\n1,lt_veh,0,7,,\n",
+        )
+        .unwrap();
+        let sample = Sample::observe(&observed, Some(&profiles));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let sample = sample.unwrap();
+
+        let values = |kind: &str, variable| sample.values.get(&(kind.to_string(), variable));
+        assert_eq!(values("lt_veh", "v_min"), Some(&vec![4.5]));
+        assert_eq!(values("lt_veh", "x_min"), Some(&vec![3.0]));
+        assert_eq!(values("lt_veh", "t_exit"), Some(&vec![9.0, 8.0]));
+        assert_eq!(values("rt_veh", "t_exit"), Some(&vec![7.0]));
+
+        let mut velocity = sample.velocity["lt_veh"].clone();
+        velocity.sort_by_key(|v| v.len());
+        assert_eq!(velocity, [vec![[0.0, 7.0]], vec![[0.0, 6.0], [1.0, 5.0]]]);
+        assert_eq!(sample.curvature["lt_veh"], [vec![[1.0, 0.0], [2.0, 0.1]]]);
+    }
+
+    #[test]
+    fn observe_requires_kind() {
+        let path = std::env::temp_dir().join(format!("report-kind-{}.csv", std::process::id()));
+        std::fs::write(&path, "v_min,x_min\n4.5,3\n").unwrap();
+        let sample = Sample::observe(&path, None);
+        std::fs::remove_file(&path).unwrap();
+        assert!(sample.is_err());
+    }
+}
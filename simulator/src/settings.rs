@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, serde::Serialize, serde::Deserialize)]
 pub enum Dir {
     NxPy,
     NxNy,
@@ -10,7 +10,8 @@ pub enum Dir {
     PyPx,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct VehFlow {
     pub src_dir: Dir,
     pub src_lane: usize,
@@ -24,6 +25,11 @@ pub struct VehFlow {
     pub padding_out_mean: f64,
     pub padding_out_stdv: f64,
     pub large_prob: f64,
+    // flows of every movement share the type, gap acceptance only reads rt_veh flows
+    // and pedestrian yielding only turning flows
+    pub critical_gap_mean: f64,
+    pub critical_gap_stdv: f64,
+    pub yield_prob: f64,
 }
 
 impl Default for VehFlow {
@@ -41,12 +47,15 @@ impl Default for VehFlow {
             padding_out_mean: 1.75,
             padding_out_stdv: 0.1,
             large_prob: 0.1,
+            critical_gap_mean: 4.5,
+            critical_gap_stdv: 0.5,
+            yield_prob: 0.8,
         }
     }
 }
 
 impl VehFlow {
-    fn show_inside(&mut self, ui: &mut egui::Ui, id_source: &str) {
+    fn show_inside(&mut self, ui: &mut egui::Ui, id_source: &str, kind: crate::forward::AgentKind) {
         ui.collapsing(format!("Vehicle flow {id_source}"), |ui| {
             egui::ComboBox::from_label(format!("Source direction {id_source}"))
                 .selected_text(format!("{:?}", self.src_dir))
@@ -108,11 +117,29 @@ impl VehFlow {
             let widget = egui::Slider::new(&mut self.large_prob, 0.0..=1.0)
                 .text("Large vehicle probability");
             ui.add(widget);
+
+            // traffic keeps left, the right turn crosses the opposing through lanes
+            if kind == crate::forward::AgentKind::RtVeh {
+                let widget = egui::Slider::new(&mut self.critical_gap_mean, 0.0..=10.0)
+                    .text("Critical gap mean, right turn across opposing traffic [s]");
+                ui.add(widget);
+
+                let widget = egui::Slider::new(&mut self.critical_gap_stdv, 0.0..=10.0)
+                    .text("Critical gap stdv, right turn across opposing traffic [s]");
+                ui.add(widget);
+            }
+
+            if kind != crate::forward::AgentKind::StVeh {
+                let widget = egui::Slider::new(&mut self.yield_prob, 0.0..=1.0)
+                    .text("Pedestrian yield probability, unless on an arrow");
+                ui.add(widget);
+            }
         });
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct PedFlow {
     pub src: Dir,
     pub dst: Dir,
@@ -206,7 +233,40 @@ impl PedFlow {
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum TurnMode {
+    #[default]
+    Permissive,
+    Protected,
+    LeadingProtectedPermissive,
+    LaggingProtectedPermissive,
+}
+
+impl TurnMode {
+    fn show_inside(&mut self, ui: &mut egui::Ui, id_source: &str) {
+        egui::ComboBox::from_label(format!("Turn mode {id_source}"))
+            .selected_text(format!("{self:?}"))
+            .show_ui(ui, |ui| {
+                // permissive right turns yield to opposing through traffic under left-hand
+                // traffic, permissive turns yield to pedestrians
+                ui.selectable_value(self, TurnMode::Permissive, "Permissive");
+                ui.selectable_value(self, TurnMode::Protected, "Protected");
+                ui.selectable_value(
+                    self,
+                    TurnMode::LeadingProtectedPermissive,
+                    "Leading protected-permissive",
+                );
+                ui.selectable_value(
+                    self,
+                    TurnMode::LaggingProtectedPermissive,
+                    "Lagging protected-permissive",
+                );
+            });
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct VehSignal {
     pub src_dir: Dir,
     pub dst_dir: Dir,
@@ -215,6 +275,9 @@ pub struct VehSignal {
     pub green_secs: f64,
     pub yellow_secs: f64,
     pub red_secs: f64,
+    pub all_red_secs: f64,
+    pub turn_mode: TurnMode,
+    pub protected_secs: f64,
 }
 
 impl Default for VehSignal {
@@ -227,6 +290,9 @@ impl Default for VehSignal {
             green_secs: 110.0,
             yellow_secs: 5.0,
             red_secs: 125.0,
+            all_red_secs: 5.0,
+            turn_mode: TurnMode::Permissive,
+            protected_secs: 10.0,
         }
     }
 }
@@ -278,11 +344,22 @@ impl VehSignal {
 
             let widget = egui::Slider::new(&mut self.red_secs, 0.0..=600.0).text("Red time[sec]");
             ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.all_red_secs, 0.0..=600.0).text("All-red time[sec]");
+            ui.add(widget);
+
+            self.turn_mode.show_inside(ui, id_source);
+
+            let widget = egui::Slider::new(&mut self.protected_secs, 0.0..=600.0)
+                .text("Protected arrow time[sec]");
+            ui.add(widget);
         });
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct PedSignal {
     pub src_dir: Dir,
     pub dst_dir: Dir,
@@ -291,6 +368,8 @@ pub struct PedSignal {
     pub green_secs: f64,
     pub blink_secs: f64,
     pub red_secs: f64,
+    pub all_red_secs: f64,
+    pub countdown: bool,
 }
 
 impl Default for PedSignal {
@@ -303,6 +382,8 @@ impl Default for PedSignal {
             green_secs: 100.0,
             blink_secs: 10.0,
             red_secs: 130.0,
+            all_red_secs: 10.0,
+            countdown: false,
         }
     }
 }
@@ -354,12 +435,325 @@ impl PedSignal {
 
             let widget = egui::Slider::new(&mut self.red_secs, 0.0..=600.0).text("Red time[sec]");
             ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.all_red_secs, 0.0..=600.0).text("All-red time[sec]");
+            ui.add(widget);
+
+            ui.checkbox(&mut self.countdown, "Countdown display");
+        });
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum ControlMode {
+    #[default]
+    Fixed,
+    Actuated,
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ActuatedControl {
+    pub min_green_secs: f64,
+    pub max_green_secs: f64,
+    pub passage_secs: f64,
+    pub yellow_secs: f64,
+    pub all_red_secs: f64,
+    pub walk_secs: f64,
+    pub blink_secs: f64,
+    pub detector_setback: f64,
+    pub detector_length: f64,
+    pub lpi_secs: f64,
+}
+
+impl Default for ActuatedControl {
+    fn default() -> Self {
+        Self {
+            min_green_secs: 10.0,
+            max_green_secs: 60.0,
+            passage_secs: 3.0,
+            yellow_secs: 3.0,
+            all_red_secs: 2.0,
+            walk_secs: 7.0,
+            blink_secs: 10.0,
+            detector_setback: 30.0,
+            detector_length: 2.0,
+            lpi_secs: 0.0,
+        }
+    }
+}
+
+impl ActuatedControl {
+    fn show_inside(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Actuated control", |ui| {
+            let widget = egui::Slider::new(&mut self.min_green_secs, 0.0..=120.0)
+                .text("Minimum green time[sec]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.max_green_secs, 0.0..=300.0)
+                .text("Maximum green time[sec]");
+            ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.passage_secs, 0.0..=10.0).text("Passage time[sec]");
+            ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.yellow_secs, 0.0..=10.0).text("Yellow time[sec]");
+            ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.all_red_secs, 0.0..=10.0).text("All-red time[sec]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.walk_secs, 0.0..=60.0).text("Walk time[sec]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.blink_secs, 0.0..=60.0)
+                .text("Pedestrian clearance time[sec]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.detector_setback, 0.0..=64.0)
+                .text("Detector setback from stop line[m]");
+            ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.detector_length, 0.0..=10.0).text("Detector length[m]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.lpi_secs, 0.0..=30.0)
+                .text("Leading pedestrian interval[sec]");
+            ui.add(widget);
         });
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+// placement of the intersection's local frame in gps coordinates
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GpsFrame {
+    pub origin_x: f64,
+    pub origin_y: f64,
+    pub rotation: f64,
+}
+
+impl GpsFrame {
+    fn show_inside(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("GPS frame", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Origin x[m]");
+                ui.add(egui::DragValue::new(&mut self.origin_x).speed(0.1));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Origin y[m]");
+                ui.add(egui::DragValue::new(&mut self.origin_y).speed(0.1));
+            });
+            let widget =
+                egui::Slider::new(&mut self.rotation, -180.0..=180.0).text("Rotation[deg]");
+            ui.add(widget);
+        });
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Phase {
+    pub ring: usize,
+    pub barrier: usize,
+    pub green_secs: f64,
+    pub yellow_secs: f64,
+    pub all_red_secs: f64,
+    pub blink_secs: f64,
+    pub lpi_secs: f64,
+    pub countdown: bool,
+    pub turn_mode: TurnMode,
+    pub protected_secs: f64,
+    pub veh_movements: Vec<[Dir; 2]>,
+    pub ped_movements: Vec<[Dir; 2]>,
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Self {
+            ring: 0,
+            barrier: 0,
+            green_secs: 110.0,
+            yellow_secs: 5.0,
+            all_red_secs: 5.0,
+            blink_secs: 10.0,
+            lpi_secs: 0.0,
+            countdown: false,
+            turn_mode: TurnMode::Permissive,
+            protected_secs: 10.0,
+            veh_movements: vec![],
+            ped_movements: vec![],
+        }
+    }
+}
+
+impl Phase {
+    fn show_inside(&mut self, ui: &mut egui::Ui, id_source: &str) {
+        ui.collapsing(format!("Phase {id_source}"), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Ring");
+                ui.add(egui::DragValue::new(&mut self.ring));
+                ui.label("Barrier");
+                ui.add(egui::DragValue::new(&mut self.barrier));
+            });
+
+            let widget =
+                egui::Slider::new(&mut self.green_secs, 0.0..=600.0).text("Green time[sec]");
+            ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.yellow_secs, 0.0..=600.0).text("Yellow time[sec]");
+            ui.add(widget);
+
+            let widget =
+                egui::Slider::new(&mut self.all_red_secs, 0.0..=600.0).text("All-red time[sec]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.blink_secs, 0.0..=600.0)
+                .text("Pedestrian blink time[sec]");
+            ui.add(widget);
+
+            let widget = egui::Slider::new(&mut self.lpi_secs, 0.0..=30.0)
+                .text("Leading pedestrian interval[sec]");
+            ui.add(widget);
+
+            ui.checkbox(&mut self.countdown, "Pedestrian countdown display");
+
+            self.turn_mode.show_inside(ui, id_source);
+
+            let widget = egui::Slider::new(&mut self.protected_secs, 0.0..=600.0)
+                .text("Protected arrow time[sec]");
+            ui.add(widget);
+
+            // movements
+            ui.horizontal(|ui| {
+                ui.label("Vehicle movements");
+                if ui.button("Add").clicked() {
+                    self.veh_movements.push([Dir::NxPy, Dir::PxPy]);
+                }
+                if ui.button("Remove").clicked() {
+                    self.veh_movements.pop();
+                }
+            });
+            for (i, [src, dst]) in self.veh_movements.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    dir_combo_box(ui, format!("veh_{id_source}_{i}_src"), src);
+                    dir_combo_box(ui, format!("veh_{id_source}_{i}_dst"), dst);
+                });
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Pedestrian movements");
+                if ui.button("Add").clicked() {
+                    self.ped_movements.push([Dir::NxNy, Dir::NxPy]);
+                }
+                if ui.button("Remove").clicked() {
+                    self.ped_movements.pop();
+                }
+            });
+            for (i, [src, dst]) in self.ped_movements.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    dir_combo_box(ui, format!("ped_{id_source}_{i}_src"), src);
+                    dir_combo_box(ui, format!("ped_{id_source}_{i}_dst"), dst);
+                });
+            }
+        });
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SignalPlan {
+    pub offset_secs: f64,
+    pub phases: Vec<Phase>,
+}
+
+impl Default for SignalPlan {
+    fn default() -> Self {
+        Self {
+            offset_secs: 0.0,
+            phases: vec![
+                // along road
+                Phase {
+                    ring: 0,
+                    barrier: 0,
+                    veh_movements: vec![
+                        [Dir::NxPy, Dir::PxPy],
+                        [Dir::NxPy, Dir::PyNx],
+                        [Dir::NxPy, Dir::NyPx],
+                    ],
+                    ped_movements: vec![[Dir::NyNx, Dir::NyPx], [Dir::PyNx, Dir::PyPx]],
+                    ..Default::default()
+                },
+                Phase {
+                    ring: 1,
+                    barrier: 0,
+                    veh_movements: vec![
+                        [Dir::PxNy, Dir::NxNy],
+                        [Dir::PxNy, Dir::NyPx],
+                        [Dir::PxNy, Dir::PyNx],
+                    ],
+                    ..Default::default()
+                },
+                // across road
+                Phase {
+                    ring: 0,
+                    barrier: 1,
+                    veh_movements: vec![
+                        [Dir::NyNx, Dir::PyNx],
+                        [Dir::NyNx, Dir::NxNy],
+                        [Dir::NyNx, Dir::PxPy],
+                    ],
+                    ped_movements: vec![[Dir::NxNy, Dir::NxPy], [Dir::PxNy, Dir::PxPy]],
+                    ..Default::default()
+                },
+                Phase {
+                    ring: 1,
+                    barrier: 1,
+                    veh_movements: vec![
+                        [Dir::PyPx, Dir::NyPx],
+                        [Dir::PyPx, Dir::PxPy],
+                        [Dir::PyPx, Dir::NxNy],
+                    ],
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+}
+
+impl SignalPlan {
+    fn show_inside(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Signal plan", |ui| {
+            let widget =
+                egui::Slider::new(&mut self.offset_secs, 0.0..=600.0).text("Offset time[sec]");
+            ui.add(widget);
+
+            ui.horizontal(|ui| {
+                ui.label("Phases");
+                if ui.button("Add").clicked() {
+                    self.phases.push(Default::default());
+                }
+                if ui.button("Remove").clicked() {
+                    self.phases.pop();
+                }
+            });
+            for (i, phase) in self.phases.iter_mut().enumerate() {
+                phase.show_inside(ui, format!("{i}").as_str());
+            }
+        });
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct Settings {
+    pub seed: u64,
     pub angle: f64,
     pub radius: f64,
     pub width_along: f64,
@@ -374,17 +768,26 @@ pub struct Settings {
     pub cw_width_across: f64,
     pub sl_setback_along: f64,
     pub sl_setback_across: f64,
+    pub st_veh_flows: Vec<VehFlow>,
     pub lt_veh_flows: Vec<VehFlow>,
     pub rt_veh_flows: Vec<VehFlow>,
     pub ped_flows: Vec<PedFlow>,
     pub ig_ped_flows: Vec<PedFlow>,
     pub veh_signals: Vec<VehSignal>,
     pub ped_signals: Vec<PedSignal>,
+    #[serde(default)]
+    pub signal_plan: Option<SignalPlan>,
+    pub control: ControlMode,
+    pub actuated: ActuatedControl,
+    pub model_path: String,
+    pub model_version: String,
+    pub gps_frame: GpsFrame,
 }
 
 impl Default for Settings {
     fn default() -> Self {
-        Self {
+        let mut settings = Self {
+            seed: 0,
             angle: 90.0,
             radius: 14.0,
             width_along: 17.0,
@@ -399,6 +802,36 @@ impl Default for Settings {
             cw_width_across: 4.5,
             sl_setback_along: 19.0,
             sl_setback_across: 19.0,
+            st_veh_flows: vec![
+                VehFlow {
+                    src_dir: Dir::NxPy,
+                    src_lane: 3,
+                    dst_dir: Dir::PxPy,
+                    dst_lane: 3,
+                    ..Default::default()
+                },
+                VehFlow {
+                    src_dir: Dir::PxNy,
+                    src_lane: 0,
+                    dst_dir: Dir::NxNy,
+                    dst_lane: 0,
+                    ..Default::default()
+                },
+                VehFlow {
+                    src_dir: Dir::PyPx,
+                    src_lane: 0,
+                    dst_dir: Dir::NyPx,
+                    dst_lane: 0,
+                    ..Default::default()
+                },
+                VehFlow {
+                    src_dir: Dir::NyNx,
+                    src_lane: 3,
+                    dst_dir: Dir::PyNx,
+                    dst_lane: 3,
+                    ..Default::default()
+                },
+            ],
             lt_veh_flows: vec![
                 VehFlow {
                     src_dir: Dir::NxPy,
@@ -543,116 +976,49 @@ impl Default for Settings {
                     ..Default::default()
                 },
             ],
-            veh_signals: vec![
-                // straight
-                VehSignal {
-                    src_dir: Dir::NxPy,
-                    dst_dir: Dir::PxPy,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::PxNy,
-                    dst_dir: Dir::NxNy,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::PyPx,
-                    dst_dir: Dir::NyPx,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::NyNx,
-                    dst_dir: Dir::PyNx,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                // left-turned
-                VehSignal {
-                    src_dir: Dir::NxPy,
-                    dst_dir: Dir::PyNx,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::PyPx,
-                    dst_dir: Dir::PxPy,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::PxNy,
-                    dst_dir: Dir::NyPx,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::NyNx,
-                    dst_dir: Dir::NxNy,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                // right-turned
-                VehSignal {
-                    src_dir: Dir::NxPy,
-                    dst_dir: Dir::NyPx,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::NyNx,
-                    dst_dir: Dir::PxPy,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::PxNy,
-                    dst_dir: Dir::PyNx,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                VehSignal {
-                    src_dir: Dir::PyPx,
-                    dst_dir: Dir::NxNy,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-            ],
-            ped_signals: vec![
-                PedSignal {
-                    src_dir: Dir::NxNy,
-                    dst_dir: Dir::NxPy,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                PedSignal {
-                    src_dir: Dir::PxNy,
-                    dst_dir: Dir::PxPy,
-                    offset_secs: 120.0,
-                    ..Default::default()
-                },
-                PedSignal {
-                    src_dir: Dir::NyNx,
-                    dst_dir: Dir::NyPx,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-                PedSignal {
-                    src_dir: Dir::PyNx,
-                    dst_dir: Dir::PyPx,
-                    offset_secs: 0.0,
-                    ..Default::default()
-                },
-            ],
-        }
+            veh_signals: vec![],
+            ped_signals: vec![],
+            signal_plan: Some(Default::default()),
+            control: ControlMode::Fixed,
+            actuated: Default::default(),
+            model_path: Default::default(),
+            model_version: crate::model::BehaviourModel::default().label(),
+            gps_frame: Default::default(),
+        };
+        settings.apply_signal_plan();
+        settings
     }
 }
 
 impl Settings {
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut settings: Self = match path.extension().and_then(|v| v.to_str()) {
+            Some("json") => serde_json::from_str(&text).map_err(invalid_data)?,
+            _ => toml::from_str(&text).map_err(invalid_data)?,
+        };
+        // stored signals may predate edits to the plan
+        settings.apply_signal_plan();
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let text = match path.extension().and_then(|v| v.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self).map_err(invalid_data)?,
+            _ => toml::to_string_pretty(self).map_err(invalid_data)?,
+        };
+        std::fs::write(path, text)
+    }
+
     pub fn show_settings_inside(&mut self, ui: &mut egui::Ui) {
+        // the plan signals also depend on the flows and lanes of the plan movements
+        let previous = self.clone();
         egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Random seed");
+                ui.add(egui::DragValue::new(&mut self.seed));
+            });
+
             let widget =
                 egui::Slider::new(&mut self.angle, 0.0..=180.0).text("Intersection angle[deg]");
             ui.add(widget);
@@ -734,6 +1100,24 @@ impl Settings {
                 .text("Across stop-line setback[m]");
             ui.add(widget);
 
+            // st_veh_flows
+            ui.horizontal(|ui| {
+                ui.label("Straight vehicle flows");
+                if ui.button("Add").clicked() {
+                    self.st_veh_flows.push(Default::default());
+                }
+                if ui.button("Remove").clicked() {
+                    self.st_veh_flows.pop();
+                }
+            });
+            for (i, flow) in self.st_veh_flows.iter_mut().enumerate() {
+                flow.show_inside(
+                    ui,
+                    format!("st_veh_flow_{i}").as_str(),
+                    crate::forward::AgentKind::StVeh,
+                );
+            }
+
             // lt_veh_flows
             ui.horizontal(|ui| {
                 ui.label("Left-turned vehicle flows");
@@ -745,7 +1129,11 @@ impl Settings {
                 }
             });
             for (i, flow) in self.lt_veh_flows.iter_mut().enumerate() {
-                flow.show_inside(ui, format!("lt_veh_flow_{i}").as_str());
+                flow.show_inside(
+                    ui,
+                    format!("lt_veh_flow_{i}").as_str(),
+                    crate::forward::AgentKind::LtVeh,
+                );
             }
 
             // rt_veh_flows
@@ -759,7 +1147,11 @@ impl Settings {
                 }
             });
             for (i, flow) in self.rt_veh_flows.iter_mut().enumerate() {
-                flow.show_inside(ui, format!("rt_veh_flow_{i}").as_str());
+                flow.show_inside(
+                    ui,
+                    format!("rt_veh_flow_{i}").as_str(),
+                    crate::forward::AgentKind::RtVeh,
+                );
             }
 
             // ped_flows
@@ -790,33 +1182,137 @@ impl Settings {
                 flow.show_inside(ui, format!("ig_ped_flow_{i}").as_str());
             }
 
-            // veh_signals
+            // behaviour model
             ui.horizontal(|ui| {
-                ui.label("Vehicle signals");
-                if ui.button("Add").clicked() {
-                    self.veh_signals.push(Default::default());
-                }
-                if ui.button("Remove").clicked() {
-                    self.veh_signals.pop();
+                ui.label("Behaviour model");
+                let widget =
+                    egui::TextEdit::singleline(&mut self.model_path).hint_text("embedded default");
+                ui.add(widget);
+                if ui.button("Load").clicked() {
+                    match crate::model::BehaviourModel::from_settings(self) {
+                        Ok(model) => {
+                            self.model_version = model.label();
+                            log::info!("loaded model {}", self.model_version);
+                        }
+                        Err(err) => log::error!("failed to load model {}: {err}", self.model_path),
+                    }
                 }
             });
-            for (i, signal) in self.veh_signals.iter_mut().enumerate() {
-                signal.show_inside(ui, format!("veh_signal_{i}").as_str());
+            ui.label(format!("Model version: {}", self.model_version));
+            self.gps_frame.show_inside(ui);
+
+            // signal control
+            egui::ComboBox::from_label("Signal control")
+                .selected_text(format!("{:?}", self.control))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.control, ControlMode::Fixed, "Fixed");
+                    ui.selectable_value(&mut self.control, ControlMode::Actuated, "Actuated");
+                });
+            self.actuated.show_inside(ui);
+
+            // signal plan
+            let mut use_plan = self.signal_plan.is_some();
+            ui.checkbox(&mut use_plan, "Generate signals from the signal plan");
+            match (use_plan, &self.signal_plan) {
+                (true, None) => self.signal_plan = Some(Default::default()),
+                (false, Some(_)) => self.signal_plan = None,
+                _ => {}
+            }
+            if let Some(plan) = &mut self.signal_plan {
+                plan.show_inside(ui);
             }
 
-            // ped_signals
-            ui.horizontal(|ui| {
-                ui.label("Pedestrian signals");
-                if ui.button("Add").clicked() {
-                    self.ped_signals.push(Default::default());
+            // signals generated from the plan are read-only
+            let editable = self.signal_plan.is_none();
+            ui.add_enabled_ui(editable, |ui| {
+                // veh_signals
+                ui.horizontal(|ui| {
+                    ui.label("Vehicle signals");
+                    if ui.button("Add").clicked() {
+                        self.veh_signals.push(Default::default());
+                    }
+                    if ui.button("Remove").clicked() {
+                        self.veh_signals.pop();
+                    }
+                });
+                for (i, signal) in self.veh_signals.iter_mut().enumerate() {
+                    signal.show_inside(ui, format!("veh_signal_{i}").as_str());
                 }
-                if ui.button("Remove").clicked() {
-                    self.ped_signals.pop();
+
+                // ped_signals
+                ui.horizontal(|ui| {
+                    ui.label("Pedestrian signals");
+                    if ui.button("Add").clicked() {
+                        self.ped_signals.push(Default::default());
+                    }
+                    if ui.button("Remove").clicked() {
+                        self.ped_signals.pop();
+                    }
+                });
+                for (i, signal) in self.ped_signals.iter_mut().enumerate() {
+                    signal.show_inside(ui, format!("ped_signal_{i}").as_str());
                 }
             });
-            for (i, signal) in self.ped_signals.iter_mut().enumerate() {
-                signal.show_inside(ui, format!("ped_signal_{i}").as_str());
-            }
         });
+
+        if *self != previous {
+            self.apply_signal_plan();
+        }
+    }
+}
+
+fn dir_combo_box(ui: &mut egui::Ui, id_source: String, dir: &mut Dir) {
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(format!("{dir:?}"))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(dir, Dir::NxPy, "-X+Y");
+            ui.selectable_value(dir, Dir::NxNy, "-X-Y");
+            ui.selectable_value(dir, Dir::PxPy, "+X+Y");
+            ui.selectable_value(dir, Dir::PxNy, "+X-Y");
+            ui.selectable_value(dir, Dir::NyNx, "-Y-X");
+            ui.selectable_value(dir, Dir::NyPx, "-Y+X");
+            ui.selectable_value(dir, Dir::PyNx, "+Y-X");
+            ui.selectable_value(dir, Dir::PyPx, "+Y+X");
+        });
+}
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a scenario off the defaults in seed, geometry, flows and control
+    fn scenario() -> Settings {
+        let mut settings = Settings {
+            seed: 20240917,
+            control: ControlMode::Actuated,
+            angle: 75.5,
+            ..Default::default()
+        };
+        settings.lt_veh_flows[0].v_in_mean = 13.1;
+        settings.ped_flows[1].density = 0.123;
+        settings.st_veh_flows[1].v_in_mean = 13.1;
+        settings.actuated.max_green_secs = 47.3;
+        settings.actuated.lpi_secs = 2.5;
+        settings
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        for settings in [Settings::default(), scenario()] {
+            let text = toml::to_string_pretty(&settings).unwrap();
+            assert_eq!(toml::from_str::<Settings>(&text).unwrap(), settings);
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for settings in [Settings::default(), scenario()] {
+            let text = serde_json::to_string_pretty(&settings).unwrap();
+            assert_eq!(serde_json::from_str::<Settings>(&text).unwrap(), settings);
+        }
     }
 }
@@ -0,0 +1,320 @@
+use crate::{settings, validator};
+
+// secs held red at the start and at the end of an active window by a hold window
+fn held_secs(cycle_secs: f64, hold: [f64; 2], offset_secs: f64, active_secs: f64) -> [f64; 2] {
+    let [start, len] = hold;
+    let w0 = (start - offset_secs).rem_euclid(cycle_secs);
+    let w1 = w0 + len;
+    if w0 < 1e-6 {
+        [w1, 0.0]
+    } else if w1 > cycle_secs + 1e-6 {
+        [w1 - cycle_secs, 0.0]
+    } else if w0 < active_secs {
+        [0.0, active_secs - w0]
+    } else {
+        [0.0, 0.0]
+    }
+}
+
+impl settings::Settings {
+    // movement signals follow the signal plan
+    pub fn apply_signal_plan(&mut self) {
+        let Some(plan) = &self.signal_plan else {
+            return;
+        };
+        let (mut veh_signals, mut ped_signals) = plan.signals();
+        plan.hold_arrow_conflicts(self, &mut veh_signals, &mut ped_signals);
+        self.veh_signals = veh_signals;
+        self.ped_signals = ped_signals;
+    }
+}
+
+impl settings::Phase {
+    pub fn duration_secs(&self) -> f64 {
+        self.green_secs + self.yellow_secs + self.all_red_secs
+    }
+}
+
+impl settings::SignalPlan {
+    pub fn barrier_count(&self) -> usize {
+        self.phases.iter().map(|v| v.barrier + 1).max().unwrap_or(0)
+    }
+
+    pub fn ring_count(&self) -> usize {
+        self.phases.iter().map(|v| v.ring + 1).max().unwrap_or(0)
+    }
+
+    // phases of a ring within a barrier, in sequence
+    pub fn sequence(&self, barrier: usize, ring: usize) -> impl Iterator<Item = &settings::Phase> {
+        self.phases
+            .iter()
+            .filter(move |v| v.barrier == barrier && v.ring == ring)
+    }
+
+    // every ring crosses a barrier together, so the longest ring sets its duration
+    pub fn barrier_secs(&self, barrier: usize) -> f64 {
+        (0..self.ring_count())
+            .map(|ring| {
+                self.sequence(barrier, ring)
+                    .map(|v| v.duration_secs())
+                    .sum()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    pub fn cycle_secs(&self) -> f64 {
+        (0..self.barrier_count())
+            .map(|barrier| self.barrier_secs(barrier))
+            .sum()
+    }
+
+    pub fn signals(&self) -> (Vec<settings::VehSignal>, Vec<settings::PedSignal>) {
+        let cycle_secs = self.cycle_secs();
+        let mut veh_signals = vec![];
+        let mut ped_signals = vec![];
+        let mut lpi_starts = vec![];
+
+        let mut barrier_start = self.offset_secs;
+        for barrier in 0..self.barrier_count() {
+            let barrier_secs = self.barrier_secs(barrier);
+
+            for ring in 0..self.ring_count() {
+                let phases: Vec<_> = self.sequence(barrier, ring).collect();
+                let ring_secs: f64 = phases.iter().map(|v| v.duration_secs()).sum();
+
+                // a shorter ring extends its last green up to the barrier
+                let mut start = barrier_start;
+                for (i, phase) in phases.iter().enumerate() {
+                    let mut green_secs = phase.green_secs;
+                    if i + 1 == phases.len() {
+                        green_secs += barrier_secs - ring_secs;
+                    }
+                    let offset_secs = start.rem_euclid(cycle_secs);
+
+                    if phase.lpi_secs > 0.0 && !phase.ped_movements.is_empty() {
+                        lpi_starts.push((offset_secs, phase.lpi_secs));
+                    }
+
+                    for &[src_dir, dst_dir] in &phase.veh_movements {
+                        // arrows only apply to turning movements
+                        let turn_mode = match validator::is_turning(src_dir, dst_dir) {
+                            true => phase.turn_mode,
+                            false => settings::TurnMode::Permissive,
+                        };
+                        veh_signals.push(settings::VehSignal {
+                            src_dir,
+                            dst_dir,
+                            cycle_secs,
+                            offset_secs,
+                            green_secs,
+                            yellow_secs: phase.yellow_secs,
+                            red_secs: cycle_secs - green_secs - phase.yellow_secs,
+                            all_red_secs: phase.all_red_secs,
+                            turn_mode,
+                            protected_secs: phase.protected_secs,
+                        });
+                    }
+
+                    // walk ends with the blink at the end of the vehicle green
+                    let blink_secs = f64::min(phase.blink_secs, green_secs);
+                    for &[src_dir, dst_dir] in &phase.ped_movements {
+                        ped_signals.push(settings::PedSignal {
+                            src_dir,
+                            dst_dir,
+                            cycle_secs,
+                            offset_secs,
+                            green_secs: green_secs - blink_secs,
+                            blink_secs,
+                            red_secs: cycle_secs - green_secs,
+                            all_red_secs: phase.yellow_secs + phase.all_red_secs,
+                            countdown: phase.countdown,
+                        });
+                    }
+
+                    start += green_secs + phase.yellow_secs + phase.all_red_secs;
+                }
+            }
+
+            barrier_start += barrier_secs;
+        }
+
+        // leading pedestrian interval holds the concurrent vehicle greens
+        for signal in &mut veh_signals {
+            let lpi = lpi_starts
+                .iter()
+                .find(|(start, _)| (start - signal.offset_secs).abs() < 1e-6);
+            if let Some(&(_, lpi_secs)) = lpi {
+                let lpi_secs = f64::min(lpi_secs, signal.green_secs);
+                signal.offset_secs = (signal.offset_secs + lpi_secs).rem_euclid(cycle_secs);
+                signal.green_secs -= lpi_secs;
+                signal.red_secs += lpi_secs;
+            }
+        }
+
+        (veh_signals, ped_signals)
+    }
+
+    // protected arrows hold the opposing and crosswalk movements they turn across red
+    pub fn hold_arrow_conflicts(
+        &self,
+        settings: &settings::Settings,
+        veh_signals: &mut [settings::VehSignal],
+        ped_signals: &mut [settings::PedSignal],
+    ) {
+        let cycle_secs = self.cycle_secs();
+        let arrows: Vec<_> = veh_signals
+            .iter()
+            .filter_map(|v| {
+                let [s0, s1] = v.arrow_window(v.green_secs)?;
+                let movement = validator::Movement::Veh([v.src_dir, v.dst_dir]);
+                Some((movement, [v.offset_secs + s0, s1 - s0]))
+            })
+            .collect();
+
+        // a hold at the start delays the green, a hold at the end clears before the arrow,
+        // a movement held for its whole green drops its clearance too
+        for &(movement, hold) in &arrows {
+            for signal in veh_signals.iter_mut() {
+                let other = validator::Movement::Veh([signal.src_dir, signal.dst_dir]);
+                if validator::conflict(settings, movement, other)
+                    != Some(validator::ConflictKind::Permissive)
+                {
+                    continue;
+                }
+                let active_secs = signal.green_secs + signal.yellow_secs;
+                let [d0, d1] = held_secs(cycle_secs, hold, signal.offset_secs, active_secs);
+                let d0 = f64::min(d0, signal.green_secs);
+                let d1 = f64::min(d1, signal.green_secs - d0);
+                signal.offset_secs = (signal.offset_secs + d0).rem_euclid(cycle_secs);
+                signal.green_secs -= d0 + d1;
+                signal.red_secs += d0 + d1;
+                if signal.green_secs < 1e-6 {
+                    signal.red_secs += signal.yellow_secs;
+                    signal.yellow_secs = 0.0;
+                }
+            }
+            for signal in ped_signals.iter_mut() {
+                let other = validator::Movement::Ped([signal.src_dir, signal.dst_dir]);
+                if validator::conflict(settings, movement, other)
+                    != Some(validator::ConflictKind::Permissive)
+                {
+                    continue;
+                }
+                let active_secs = signal.green_secs + signal.blink_secs;
+                let [d0, d1] = held_secs(cycle_secs, hold, signal.offset_secs, active_secs);
+                let d0 = f64::min(d0, signal.green_secs);
+                let d1 = f64::min(d1, signal.green_secs - d0);
+                signal.offset_secs = (signal.offset_secs + d0).rem_euclid(cycle_secs);
+                signal.green_secs -= d0 + d1;
+                signal.red_secs += d0 + d1;
+                if signal.green_secs < 1e-6 {
+                    signal.red_secs += signal.blink_secs;
+                    signal.blink_secs = 0.0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::{Dir, Settings, SignalPlan, TurnMode};
+    use crate::validator;
+
+    #[test]
+    fn signals_follow_barriers() {
+        let plan = SignalPlan::default();
+        assert_eq!(plan.cycle_secs(), 240.0);
+
+        let (veh_signals, ped_signals) = plan.signals();
+        assert_eq!(veh_signals.len(), 12);
+        assert_eq!(ped_signals.len(), 4);
+        for signal in &veh_signals {
+            let total_secs = signal.green_secs + signal.yellow_secs + signal.red_secs;
+            assert_eq!(total_secs, 240.0);
+            let offset_secs = match signal.src_dir {
+                Dir::NxPy | Dir::PxNy => 0.0,
+                _ => 120.0,
+            };
+            assert_eq!(signal.offset_secs, offset_secs);
+        }
+        for signal in &ped_signals {
+            assert_eq!(signal.green_secs, 100.0);
+            assert_eq!(signal.blink_secs, 10.0);
+            assert_eq!(
+                signal.green_secs + signal.blink_secs + signal.red_secs,
+                240.0
+            );
+        }
+    }
+
+    #[test]
+    fn shorter_ring_extends_green() {
+        let mut plan = SignalPlan::default();
+        plan.phases[1].green_secs = 90.0;
+
+        let (veh_signals, _) = plan.signals();
+        assert_eq!(plan.cycle_secs(), 240.0);
+        for signal in veh_signals.iter().filter(|v| v.src_dir == Dir::PxNy) {
+            assert_eq!(signal.green_secs, 110.0);
+        }
+    }
+
+    #[test]
+    fn lpi_delays_vehicle_green() {
+        let mut plan = SignalPlan::default();
+        plan.phases[0].lpi_secs = 4.0;
+
+        let (veh_signals, ped_signals) = plan.signals();
+        for signal in veh_signals.iter().filter(|v| v.src_dir == Dir::NxPy) {
+            assert_eq!(signal.offset_secs, 4.0);
+            assert_eq!(signal.green_secs, 106.0);
+        }
+        let signal = ped_signals.iter().find(|v| v.src_dir == Dir::NyNx).unwrap();
+        assert_eq!(signal.offset_secs, 0.0);
+    }
+
+    #[test]
+    fn leading_arrow_holds_conflicts() {
+        let mut settings = Settings::default();
+        let plan = settings.signal_plan.as_mut().unwrap();
+        for phase in &mut plan.phases {
+            phase.turn_mode = TurnMode::LeadingProtectedPermissive;
+        }
+        settings.apply_signal_plan();
+
+        // opposing movements start after the arrow
+        let held = settings.veh_signals.iter().filter(|v| v.green_secs < 110.0);
+        assert!(held.clone().count() > 0);
+        for signal in held {
+            assert_eq!(signal.green_secs, 100.0);
+            assert_eq!(signal.red_secs, 135.0);
+        }
+        assert!(validator::validate_signals(&settings).is_empty());
+    }
+
+    #[test]
+    fn held_movement_drops_clearance() {
+        let mut settings = Settings::default();
+        let plan = settings.signal_plan.as_mut().unwrap();
+        for phase in &mut plan.phases {
+            phase.turn_mode = TurnMode::Protected;
+        }
+        settings.apply_signal_plan();
+
+        let held: Vec<_> = settings
+            .veh_signals
+            .iter()
+            .filter(|v| v.green_secs == 0.0)
+            .collect();
+        assert!(!held.is_empty());
+        for signal in held {
+            assert_eq!(signal.yellow_secs, 0.0);
+            assert_eq!(signal.red_secs, signal.cycle_secs);
+        }
+        for signal in settings.ped_signals.iter().filter(|v| v.green_secs == 0.0) {
+            assert_eq!(signal.blink_secs, 0.0);
+            assert_eq!(signal.red_secs, signal.cycle_secs);
+        }
+    }
+}
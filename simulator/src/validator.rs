@@ -0,0 +1,608 @@
+use crate::{interaction, settings};
+
+use settings::Dir;
+
+pub const PASSAGE_SECS: f64 = 3.0;
+pub const CLEAR_SPEED: f64 = 10.0;
+pub const ENTRY_SPEED: f64 = 11.1;
+pub const PED_CLEAR_SPEED: f64 = 1.2;
+
+// horizon when signals do not share a cycle
+pub const HORIZON_SECS: f64 = 3600.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Movement {
+    Veh([Dir; 2]),
+    Ped([Dir; 2]),
+}
+
+impl std::fmt::Display for Movement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Movement::Veh([src, dst]) => write!(f, "Veh {src:?}-{dst:?}"),
+            Movement::Ped([src, dst]) => write!(f, "Ped {src:?}-{dst:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ConflictKind {
+    // crossing or merging streams that must never run together
+    Primary,
+    // turning streams that yield to opposing traffic or pedestrians
+    Permissive,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Violation {
+    ConflictingGreen {
+        movements: [Movement; 2],
+        start_secs: f64,
+        end_secs: f64,
+    },
+    Intergreen {
+        movements: [Movement; 2],
+        secs: f64,
+        intergreen_secs: f64,
+        required_secs: f64,
+    },
+    AllRed {
+        movements: [Movement; 2],
+        secs: f64,
+        red_secs: f64,
+        all_red_secs: f64,
+    },
+    Cycle {
+        movement: Movement,
+        total_secs: f64,
+        cycle_secs: f64,
+    },
+    AllRedExceedsRed {
+        movement: Movement,
+        red_secs: f64,
+        all_red_secs: f64,
+    },
+    DuplicateMovement {
+        movement: Movement,
+        phases: [usize; 2],
+    },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::ConflictingGreen {
+                movements: [a, b],
+                start_secs,
+                end_secs,
+            } => write!(
+                f,
+                "conflicting green {a} / {b} from {start_secs:.1} to {end_secs:.1} sec"
+            ),
+            Violation::Intergreen {
+                movements: [a, b],
+                secs,
+                intergreen_secs,
+                required_secs,
+            } => write!(
+                f,
+                "intergreen {a} -> {b} at {secs:.1} sec is {intergreen_secs:.1} sec, \
+                 requires {required_secs:.1} sec"
+            ),
+            Violation::AllRed {
+                movements: [a, b],
+                secs,
+                red_secs,
+                all_red_secs,
+            } => write!(
+                f,
+                "all-red {a} -> {b} at {secs:.1} sec is {red_secs:.1} sec, \
+                 requires {all_red_secs:.1} sec"
+            ),
+            Violation::Cycle {
+                movement,
+                total_secs,
+                cycle_secs,
+            } => write!(
+                f,
+                "{movement} green, clearance and red sum to {total_secs:.1} sec, \
+                 cycle is {cycle_secs:.1} sec"
+            ),
+            Violation::AllRedExceedsRed {
+                movement,
+                red_secs,
+                all_red_secs,
+            } => write!(
+                f,
+                "{movement} all-red {all_red_secs:.1} sec exceeds red {red_secs:.1} sec"
+            ),
+            Violation::DuplicateMovement {
+                movement,
+                phases: [a, b],
+            } => write!(f, "{movement} is assigned to both phase {a} and phase {b}"),
+        }
+    }
+}
+
+// position of the arm sides counterclockwise around the intersection
+fn circle_index(dir: Dir) -> usize {
+    match dir {
+        Dir::PxNy => 0,
+        Dir::PxPy => 1,
+        Dir::PyPx => 2,
+        Dir::PyNx => 3,
+        Dir::NxPy => 4,
+        Dir::NxNy => 5,
+        Dir::NyNx => 6,
+        Dir::NyPx => 7,
+    }
+}
+
+fn arm_index(dir: Dir) -> usize {
+    circle_index(dir) / 2
+}
+
+pub fn is_turning(src: Dir, dst: Dir) -> bool {
+    (arm_index(src) + 2) % 4 != arm_index(dst)
+}
+
+fn is_along(dir: Dir) -> bool {
+    matches!(dir, Dir::NxPy | Dir::NxNy | Dir::PxPy | Dir::PxNy)
+}
+
+// strictly inside the counterclockwise arc from a to b
+fn between(a: usize, b: usize, c: usize) -> bool {
+    let len = (b + 8 - a) % 8;
+    let pos = (c + 8 - a) % 8;
+    0 < pos && pos < len
+}
+
+// destination lanes used by the flows of a movement
+fn dst_lanes(settings: &settings::Settings, src: Dir, dst: Dir) -> Vec<usize> {
+    let flows = settings.st_veh_flows.iter();
+    let flows = flows
+        .chain(&settings.lt_veh_flows)
+        .chain(&settings.rt_veh_flows);
+    flows
+        .filter(|v| v.src_dir == src && v.dst_dir == dst)
+        .map(|v| v.dst_lane)
+        .collect()
+}
+
+// movements as chords of a circle, crossing chords conflict
+pub fn conflict(settings: &settings::Settings, a: Movement, b: Movement) -> Option<ConflictKind> {
+    match (a, b) {
+        (Movement::Veh([a_src, a_dst]), Movement::Veh([b_src, b_dst])) => {
+            if a_src == b_src {
+                return None;
+            }
+            let [a0, a1] = [circle_index(a_src), circle_index(a_dst)];
+            let [b0, b1] = [circle_index(b_src), circle_index(b_dst)];
+            // merging into the same lane, any lane without flows to tell
+            let a_lanes = dst_lanes(settings, a_src, a_dst);
+            let b_lanes = dst_lanes(settings, b_src, b_dst);
+            let merging = a_dst == b_dst
+                && (a_lanes.is_empty()
+                    || b_lanes.is_empty()
+                    || a_lanes.iter().any(|v| b_lanes.contains(v)));
+            let crossing = between(a0, a1, b0) != between(a0, a1, b1)
+                && ![a0, a1].contains(&b0)
+                && ![a0, a1].contains(&b1);
+            if !merging && !crossing {
+                return None;
+            }
+            let opposing = (arm_index(a_src) + 2) % 4 == arm_index(b_src);
+            match opposing {
+                true => Some(ConflictKind::Permissive),
+                false => Some(ConflictKind::Primary),
+            }
+        }
+        (Movement::Veh([src, dst]), Movement::Ped([cw, _]))
+        | (Movement::Ped([cw, _]), Movement::Veh([src, dst])) => {
+            let arm = arm_index(cw);
+            if arm != arm_index(src) && arm != arm_index(dst) {
+                return None;
+            }
+            match is_turning(src, dst) {
+                true => Some(ConflictKind::Permissive),
+                false => Some(ConflictKind::Primary),
+            }
+        }
+        (Movement::Ped(_), Movement::Ped(_)) => None,
+    }
+}
+
+fn sl_setback(settings: &settings::Settings, dir: Dir) -> f64 {
+    match is_along(dir) {
+        true => settings.sl_setback_along,
+        false => settings.sl_setback_across,
+    }
+}
+
+// setback, width and length of the crosswalk on the arm
+fn crosswalk(settings: &settings::Settings, dir: Dir) -> [f64; 3] {
+    match is_along(dir) {
+        true => [
+            settings.cw_setback_along,
+            settings.cw_width_along,
+            settings.width_along,
+        ],
+        false => [
+            settings.cw_setback_across,
+            settings.cw_width_across,
+            settings.width_across,
+        ],
+    }
+}
+
+// clearance minus entry time, clearing at the end of green of a and entering at green of b
+pub fn required_intergreen(settings: &settings::Settings, a: Movement, b: Movement) -> f64 {
+    let secs = match (a, b) {
+        (Movement::Veh([a_src, _]), Movement::Veh([b_src, _])) => {
+            let s_clear = sl_setback(settings, a_src) + interaction::VEH_LENGTH;
+            let s_entry = sl_setback(settings, b_src);
+            PASSAGE_SECS + s_clear / CLEAR_SPEED - s_entry / ENTRY_SPEED
+        }
+        (Movement::Veh([src, _]), Movement::Ped([cw, _])) => {
+            let [cw_setback, cw_width, _] = crosswalk(settings, cw);
+            let s_crosswalk = match arm_index(src) == arm_index(cw) {
+                true => sl_setback(settings, src) - cw_setback,
+                false => sl_setback(settings, src) + cw_setback,
+            };
+            let s_clear = s_crosswalk + cw_width + interaction::VEH_LENGTH;
+            PASSAGE_SECS + s_clear / CLEAR_SPEED
+        }
+        (Movement::Ped([cw, _]), Movement::Veh([src, _])) => {
+            let [cw_setback, _, cw_length] = crosswalk(settings, cw);
+            let s_entry = match arm_index(src) == arm_index(cw) {
+                true => sl_setback(settings, src) - cw_setback,
+                false => sl_setback(settings, src) + cw_setback,
+            };
+            cw_length / PED_CLEAR_SPEED - s_entry / ENTRY_SPEED
+        }
+        (Movement::Ped(_), Movement::Ped(_)) => 0.0,
+    };
+    f64::max(secs, 0.0)
+}
+
+struct Timing {
+    movement: Movement,
+    cycle_secs: f64,
+    offset_secs: f64,
+    green_secs: f64,
+    active_secs: f64,
+    red_secs: f64,
+    all_red_secs: f64,
+    arrow: Option<[f64; 2]>,
+}
+
+impl Timing {
+    // windows starting from one cycle before the horizon start up to its end
+    fn windows(&self, len: f64, horizon_secs: f64) -> Vec<[f64; 2]> {
+        self.windows_at(0.0, len, horizon_secs)
+    }
+
+    fn windows_at(&self, shift: f64, len: f64, horizon_secs: f64) -> Vec<[f64; 2]> {
+        if self.cycle_secs <= 0.0 {
+            return vec![];
+        }
+        let mut windows = vec![];
+        let mut start = (self.offset_secs + shift).rem_euclid(self.cycle_secs) - self.cycle_secs;
+        while start < horizon_secs {
+            windows.push([start, start + len]);
+            start += self.cycle_secs;
+        }
+        windows
+    }
+}
+
+// overlapping windows clipped to the horizon
+fn overlaps(a: &[[f64; 2]], b: &[[f64; 2]], horizon_secs: f64) -> Vec<[f64; 2]> {
+    let mut overlaps = vec![];
+    for &[a0, a1] in a {
+        for &[b0, b1] in b {
+            let start_secs = f64::max(f64::max(a0, b0), 0.0);
+            let end_secs = f64::min(f64::min(a1, b1), horizon_secs);
+            if start_secs < end_secs {
+                overlaps.push([start_secs, end_secs]);
+            }
+        }
+    }
+    overlaps
+}
+
+// a movement gets one signal, so it belongs to a single phase of the plan
+fn duplicate_movements(plan: &settings::SignalPlan) -> Vec<Violation> {
+    let mut phases = ahash::AHashMap::new();
+    let mut violations = vec![];
+    for (i, phase) in plan.phases.iter().enumerate() {
+        let veh_movements = phase.veh_movements.iter().map(|v| Movement::Veh(*v));
+        let ped_movements = phase.ped_movements.iter().map(|v| Movement::Ped(*v));
+        for movement in veh_movements.chain(ped_movements) {
+            if let Some(&j) = phases.get(&movement) {
+                violations.push(Violation::DuplicateMovement {
+                    movement,
+                    phases: [j, i],
+                });
+            } else {
+                phases.insert(movement, i);
+            }
+        }
+    }
+    violations
+}
+
+pub fn validate_signals(settings: &settings::Settings) -> Vec<Violation> {
+    let veh_timings = settings.veh_signals.iter().map(|v| Timing {
+        movement: Movement::Veh([v.src_dir, v.dst_dir]),
+        cycle_secs: v.cycle_secs,
+        offset_secs: v.offset_secs,
+        green_secs: v.green_secs,
+        active_secs: v.green_secs + v.yellow_secs,
+        red_secs: v.red_secs,
+        all_red_secs: v.all_red_secs,
+        arrow: v.arrow_window(v.green_secs),
+    });
+    let ped_timings = settings.ped_signals.iter().map(|v| Timing {
+        movement: Movement::Ped([v.src_dir, v.dst_dir]),
+        cycle_secs: v.cycle_secs,
+        offset_secs: v.offset_secs,
+        green_secs: v.green_secs,
+        active_secs: v.green_secs + v.blink_secs,
+        red_secs: v.red_secs,
+        all_red_secs: v.all_red_secs,
+        arrow: None,
+    });
+    let timings: Vec<_> = veh_timings.chain(ped_timings).collect();
+
+    let cycle_secs = timings.first().map(|v| v.cycle_secs).unwrap_or(0.0);
+    let horizon_secs = match timings.iter().all(|v| v.cycle_secs == cycle_secs) {
+        true => cycle_secs,
+        false => HORIZON_SECS,
+    };
+
+    let mut violations = match &settings.signal_plan {
+        Some(plan) => duplicate_movements(plan),
+        None => vec![],
+    };
+
+    // green, clearance and red fill the cycle
+    for timing in &timings {
+        let total_secs = timing.active_secs + timing.red_secs;
+        if (total_secs - timing.cycle_secs).abs() > 1e-6 {
+            violations.push(Violation::Cycle {
+                movement: timing.movement,
+                total_secs,
+                cycle_secs: timing.cycle_secs,
+            });
+        }
+        if timing.all_red_secs > timing.red_secs + 1e-6 {
+            violations.push(Violation::AllRedExceedsRed {
+                movement: timing.movement,
+                red_secs: timing.red_secs,
+                all_red_secs: timing.all_red_secs,
+            });
+        }
+    }
+
+    for (i, a) in timings.iter().enumerate() {
+        for (j, b) in timings.iter().enumerate() {
+            let kind = conflict(settings, a.movement, b.movement);
+
+            // a protected arrow turns a permissive conflict into a primary one
+            if kind == Some(ConflictKind::Permissive) {
+                let Some([s0, s1]) = a.arrow else {
+                    continue;
+                };
+                let a_windows = a.windows_at(s0, s1 - s0, horizon_secs);
+                let b_windows = b.windows(b.active_secs, horizon_secs);
+                for [start_secs, end_secs] in overlaps(&a_windows, &b_windows, horizon_secs) {
+                    violations.push(Violation::ConflictingGreen {
+                        movements: [a.movement, b.movement],
+                        start_secs,
+                        end_secs,
+                    });
+                }
+                continue;
+            }
+            if kind != Some(ConflictKind::Primary) {
+                continue;
+            }
+
+            // simultaneous green or clearance
+            if i < j {
+                let a_windows = a.windows(a.active_secs, horizon_secs);
+                let b_windows = b.windows(b.active_secs, horizon_secs);
+                for [start_secs, end_secs] in overlaps(&a_windows, &b_windows, horizon_secs) {
+                    violations.push(Violation::ConflictingGreen {
+                        movements: [a.movement, b.movement],
+                        start_secs,
+                        end_secs,
+                    });
+                }
+            }
+
+            // end of green of a to the next start of green of b
+            let a_windows = a.windows(a.green_secs, horizon_secs + a.cycle_secs);
+            let b_windows = b.windows(b.green_secs, horizon_secs + b.cycle_secs);
+            let required_secs = required_intergreen(settings, a.movement, b.movement);
+            for (k, [_, a_end]) in a_windows.iter().copied().enumerate() {
+                if !(0.0..horizon_secs).contains(&a_end) {
+                    continue;
+                }
+                let a_next = a_windows.get(k + 1).map(|v| v[0]).unwrap_or(f64::INFINITY);
+                let b_start = b_windows.iter().map(|v| v[0]).find(|v| *v >= a_end);
+                let Some(b_start) = b_start.filter(|v| *v < a_next) else {
+                    continue;
+                };
+                let intergreen_secs = b_start - a_end;
+
+                // no conflicting green during the all-red after the clearance
+                let red_start = a_end + a.active_secs - a.green_secs;
+                let red_secs = b_start - red_start;
+                if (0.0..a.all_red_secs - 1e-6).contains(&red_secs) {
+                    violations.push(Violation::AllRed {
+                        movements: [a.movement, b.movement],
+                        secs: red_start,
+                        red_secs,
+                        all_red_secs: a.all_red_secs,
+                    });
+                }
+
+                if intergreen_secs < required_secs - 1e-6 {
+                    violations.push(Violation::Intergreen {
+                        movements: [a.movement, b.movement],
+                        secs: a_end,
+                        intergreen_secs,
+                        required_secs,
+                    });
+                }
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_signals_are_valid() {
+        let settings = settings::Settings::default();
+        assert_eq!(validate_signals(&settings), vec![]);
+    }
+
+    #[test]
+    fn crossing_movements_conflict() {
+        let settings = settings::Settings::default();
+        let along = Movement::Veh([Dir::NxPy, Dir::PxPy]);
+        let across = Movement::Veh([Dir::NyNx, Dir::PyNx]);
+        let opposing = Movement::Veh([Dir::PxNy, Dir::NxNy]);
+        let opposing_rt = Movement::Veh([Dir::PxNy, Dir::PyNx]);
+        let opposing_lt = Movement::Veh([Dir::PxNy, Dir::NyPx]);
+
+        assert_eq!(
+            conflict(&settings, along, across),
+            Some(ConflictKind::Primary)
+        );
+        assert_eq!(
+            conflict(&settings, across, along),
+            Some(ConflictKind::Primary)
+        );
+        assert_eq!(conflict(&settings, along, opposing), None);
+        assert_eq!(conflict(&settings, along, along), None);
+        // left-hand traffic, the right turn crosses the opposing through movement
+        assert_eq!(
+            conflict(&settings, along, opposing_rt),
+            Some(ConflictKind::Permissive)
+        );
+        assert_eq!(conflict(&settings, along, opposing_lt), None);
+    }
+
+    #[test]
+    fn crosswalk_conflicts() {
+        let settings = settings::Settings::default();
+        let crosswalk = Movement::Ped([Dir::NyNx, Dir::NyPx]);
+        let through = Movement::Veh([Dir::NyNx, Dir::PyNx]);
+        let turning = Movement::Veh([Dir::NxPy, Dir::NyPx]);
+        let away = Movement::Veh([Dir::NxPy, Dir::PxPy]);
+
+        assert_eq!(
+            conflict(&settings, through, crosswalk),
+            Some(ConflictKind::Primary)
+        );
+        assert_eq!(
+            conflict(&settings, crosswalk, turning),
+            Some(ConflictKind::Permissive)
+        );
+        assert_eq!(conflict(&settings, away, crosswalk), None);
+        assert_eq!(conflict(&settings, crosswalk, crosswalk), None);
+    }
+
+    #[test]
+    fn simultaneous_green_conflicts() {
+        let mut settings = settings::Settings {
+            signal_plan: None,
+            ..Default::default()
+        };
+        for signal in &mut settings.veh_signals {
+            signal.offset_secs = 0.0;
+        }
+
+        let violations = validate_signals(&settings);
+        assert!(violations.iter().any(|v| matches!(
+            v,
+            Violation::ConflictingGreen {
+                movements: [
+                    Movement::Veh([Dir::NxPy, Dir::PxPy]),
+                    Movement::Veh([Dir::NyNx, Dir::PyNx])
+                ],
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn short_clearance_breaks_intergreen() {
+        let mut settings = settings::Settings::default();
+        let plan = settings.signal_plan.as_mut().unwrap();
+        plan.phases[0].yellow_secs = 1.0;
+        plan.phases[0].all_red_secs = 0.0;
+        plan.phases[1].yellow_secs = 1.0;
+        plan.phases[1].all_red_secs = 0.0;
+        settings.apply_signal_plan();
+
+        let violations = validate_signals(&settings);
+        let intergreen = violations.iter().find_map(|v| match v {
+            Violation::Intergreen {
+                movements:
+                    [Movement::Veh([Dir::NxPy, Dir::PxPy]), Movement::Veh([Dir::NyNx, Dir::PyNx])],
+                intergreen_secs,
+                required_secs,
+                ..
+            } => Some((*intergreen_secs, *required_secs)),
+            _ => None,
+        });
+        let (intergreen_secs, required_secs) = intergreen.unwrap();
+        assert!((intergreen_secs - 1.0).abs() < 1e-6);
+        assert!(required_secs > intergreen_secs);
+        assert!(violations
+            .iter()
+            .all(|v| !matches!(v, Violation::Cycle { .. })));
+    }
+
+    #[test]
+    fn signal_must_fill_cycle() {
+        let mut settings = settings::Settings {
+            signal_plan: None,
+            ..Default::default()
+        };
+        settings.veh_signals[0].red_secs -= 10.0;
+
+        let violations = validate_signals(&settings);
+        assert!(violations.contains(&Violation::Cycle {
+            movement: Movement::Veh([
+                settings.veh_signals[0].src_dir,
+                settings.veh_signals[0].dst_dir
+            ]),
+            total_secs: 230.0,
+            cycle_secs: 240.0,
+        }));
+    }
+
+    #[test]
+    fn movement_in_two_phases() {
+        let mut settings = settings::Settings::default();
+        let plan = settings.signal_plan.as_mut().unwrap();
+        let movement = plan.phases[0].veh_movements[0];
+        plan.phases[2].veh_movements.push(movement);
+        settings.apply_signal_plan();
+
+        let violations = validate_signals(&settings);
+        assert!(violations.contains(&Violation::DuplicateMovement {
+            movement: Movement::Veh(movement),
+            phases: [0, 2],
+        }));
+    }
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use parking_lot::Mutex;
 
 use crate::forward;
+use crate::gps;
 use crate::settings;
 
 pub const LOOP_WAIT: f64 = 0.016;
@@ -10,13 +11,23 @@ pub const TIME_SCALE: f64 = 10.0;
 
 pub struct Widget {
     pub setting: settings::Settings,
+    pub scenario_path: String,
+    pub gps_path: String,
+    pub gps_header: bool,
+    pub gps_utc: bool,
+    pub gps_runs: Vec<gps::Run>,
     pub forward: Arc<Mutex<Option<forward::Forward>>>,
 }
 
 impl Widget {
-    pub fn new() -> Self {
+    pub fn new(setting: settings::Settings, scenario_path: String) -> Self {
         Self {
-            setting: Default::default(),
+            setting,
+            scenario_path,
+            gps_path: Default::default(),
+            gps_header: false,
+            gps_utc: false,
+            gps_runs: Default::default(),
             forward: Default::default(),
         }
     }
@@ -26,6 +37,59 @@ impl Widget {
         widget.show(ctx, |ui| {
             ui.heading("Parametr Settings");
 
+            ui.horizontal(|ui| {
+                ui.label("Scenario");
+                ui.text_edit_singleline(&mut self.scenario_path);
+            });
+            ui.horizontal(|ui| {
+                let path = std::path::Path::new(&self.scenario_path);
+
+                if ui.button("Save").clicked() {
+                    match self.setting.save(path) {
+                        Ok(()) => log::info!("saved scenario {}", path.display()),
+                        Err(err) => log::error!("failed to save {}: {err}", path.display()),
+                    }
+                }
+
+                if ui.button("Load").clicked() {
+                    match settings::Settings::load(path) {
+                        Ok(setting) => {
+                            self.setting = setting;
+                            log::info!("loaded scenario {}", path.display());
+                        }
+                        Err(err) => log::error!("failed to load {}: {err}", path.display()),
+                    }
+                }
+            });
+
+            // observed runs overlaid on the simulation
+            ui.horizontal(|ui| {
+                ui.label("GPS");
+                ui.text_edit_singleline(&mut self.gps_path);
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.gps_header, "Header");
+                ui.checkbox(&mut self.gps_utc, "UTC");
+
+                if ui.button("Import").clicked() {
+                    let path = std::path::PathBuf::from(&self.gps_path);
+                    let frame = &self.setting.gps_frame;
+                    let paths = std::slice::from_ref(&path);
+                    match gps::import(paths, frame, self.gps_header, self.gps_utc) {
+                        Ok(runs) => {
+                            log::info!("imported {} runs from {}", runs.len(), path.display());
+                            self.gps_runs.extend(runs);
+                        }
+                        Err(err) => log::error!("failed to import {}: {err}", path.display()),
+                    }
+                }
+
+                if ui.button("Clear").clicked() {
+                    self.gps_runs.clear();
+                }
+            });
+            ui.separator();
+
             self.setting.show_settings_inside(ui);
         });
 
@@ -53,8 +117,10 @@ impl Widget {
                 ui.label(widget);
 
                 if ui.button("New Simulation").clicked() {
-                    let forward = forward::Forward::new(self.setting.clone());
-                    *self.forward.lock() = Some(forward);
+                    match forward::Forward::new(self.setting.clone()) {
+                        Ok(forward) => *self.forward.lock() = Some(forward),
+                        Err(err) => log::error!("{err}"),
+                    }
                 }
 
                 if ui.button("Drop Simulation").clicked() {
@@ -62,10 +128,23 @@ impl Widget {
                 }
             });
 
+            let lines: Vec<_> = self
+                .gps_runs
+                .iter()
+                .map(|run| {
+                    egui_plot::Line::new(run.trajectory_series.clone())
+                        .color(egui::Color32::LIGHT_BLUE)
+                        .name(&run.file)
+                })
+                .collect();
+            let overlay_fn = |plot_ui: &mut egui_plot::PlotUi| {
+                lines.into_iter().for_each(|v| plot_ui.line(v));
+            };
+
             if let Some(forward) = self.forward.lock().as_mut() {
-                forward.show_simulation_inside(ui);
+                forward.show_simulation_inside(ui, overlay_fn);
             } else {
-                self.setting.show_simulation_inside(ui, |_| {});
+                self.setting.show_simulation_inside(ui, overlay_fn);
             }
         })
     }
@@ -82,7 +161,7 @@ impl Widget {
                     break 'scope;
                 };
 
-                let container = std::mem::replace(&mut instant, Some(std::time::Instant::now()));
+                let container = instant.replace(std::time::Instant::now());
 
                 let Some(instant) = container.as_ref() else {
                     break 'scope;
@@ -90,6 +169,8 @@ impl Widget {
 
                 let delta_time = instant.elapsed().as_secs_f64() * TIME_SCALE;
                 forward.forward(delta_time);
+                // nothing reads exited agents in the viewer
+                forward.drain_archive();
             }
             std::thread::sleep(std::time::Duration::from_secs_f64(LOOP_WAIT));
         })